@@ -0,0 +1,360 @@
+use std::collections::BTreeMap;
+use std::io::{self, BufReader, Cursor, Read, Seek, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Mutex;
+
+use flate2::read::GzDecoder;
+use tempfile::NamedTempFile;
+
+use crate::error::Error;
+use crate::source::{list_file_paths, ImageReader, Listing, MinutesSource};
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub enum ArchiveKind {
+    Zip,
+    Tar,
+    TarGz,
+}
+
+impl ArchiveKind {
+    /// Detects the kind of archive by the file name, e.g. `photos.zip` or `photos.tar.gz`.
+    pub fn of(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_string_lossy().to_lowercase();
+        if name.ends_with(".zip") {
+            Some(ArchiveKind::Zip)
+        } else if name.ends_with(".tar") {
+            Some(ArchiveKind::Tar)
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            Some(ArchiveKind::TarGz)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+enum EntryLocation {
+    Zip { index: usize },
+    Tar { offset: u64, size: u64 },
+}
+
+/// What entries are read from, prepared once when the archive is opened.
+#[derive(Debug)]
+enum ArchiveData {
+    /// Parsed only once, reading an entry locks it.
+    Zip(Mutex<zip::ZipArchive<BufReader<fs_err::File>>>),
+    Tar,
+    /// Compressed TAR archives cannot be read at an offset, so they are decompressed
+    /// into a temporary file that is read like a TAR archive.
+    Decompressed(NamedTempFile),
+}
+
+/// The files of a ZIP or (compressed) TAR archive. Entries are read from the archive
+/// when needed instead of being extracted, but the archive is only parsed or
+/// decompressed once.
+///
+/// A single top-level directory that contains everything else (as created by
+/// compressing a folder) is not part of the entry names.
+#[derive(Debug)]
+pub struct Archive {
+    path: PathBuf,
+    kind: ArchiveKind,
+    entries: BTreeMap<PathBuf, EntryLocation>,
+    data: ArchiveData,
+}
+
+impl Archive {
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        let archive_error = |source| Error::Archive {
+            path: path.to_path_buf(),
+            source,
+        };
+        let kind = ArchiveKind::of(path).ok_or_else(|| {
+            archive_error(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "not a .zip, .tar, .tar.gz or .tgz archive",
+            ))
+        })?;
+        let (entries, data) = match kind {
+            ArchiveKind::Zip => read_zip(path),
+            ArchiveKind::Tar => list_tar_entries(path).map(|entries| (entries, ArchiveData::Tar)),
+            ArchiveKind::TarGz => decompress_tar(path),
+        }
+        .map_err(archive_error)?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            kind,
+            entries: strip_common_directory(entries),
+            data,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn kind(&self) -> ArchiveKind {
+        self.kind
+    }
+
+    pub fn read(&self, entry: &Path) -> Result<Vec<u8>, Error> {
+        let location = self
+            .entries
+            .get(entry)
+            .ok_or_else(|| Error::SourceMissing {
+                path: self.path.join(entry),
+            })?;
+        self.read_location(location)
+            .map_err(Error::read(self.path.join(entry)))
+    }
+
+    fn read_location(&self, location: &EntryLocation) -> io::Result<Vec<u8>> {
+        let mut content = vec![];
+        match (location, &self.data) {
+            (EntryLocation::Zip { index }, ArchiveData::Zip(archive)) => {
+                let mut archive = archive.lock().unwrap_or_else(|e| e.into_inner());
+                archive.by_index(*index)?.read_to_end(&mut content)?;
+            }
+            (EntryLocation::Tar { offset, size }, data) => {
+                let path = match data {
+                    ArchiveData::Decompressed(file) => file.path(),
+                    _ => &self.path,
+                };
+                let mut file = open_file(path)?;
+                file.seek(SeekFrom::Start(*offset))?;
+                file.take(*size).read_to_end(&mut content)?;
+            }
+            (EntryLocation::Zip { .. }, _) => {
+                unreachable!("ZIP entries are only listed for ZIP archives")
+            }
+        }
+        Ok(content)
+    }
+}
+
+impl MinutesSource for Archive {
+    fn root(&self) -> &Path {
+        &self.path
+    }
+
+    fn list(&self, dir: &Path) -> Result<Listing, Error> {
+        Ok(list_file_paths(self.entries.keys(), dir))
+    }
+
+    fn open(&self, file: &Path) -> Result<Box<dyn ImageReader>, Error> {
+        Ok(Box::new(Cursor::new(self.read(file)?)))
+    }
+
+    fn contains_file(&self, file: &Path) -> Result<bool, Error> {
+        Ok(self.entries.contains_key(file))
+    }
+}
+
+fn open_file(path: &Path) -> io::Result<BufReader<fs_err::File>> {
+    Ok(BufReader::new(fs_err::File::open(path)?))
+}
+
+fn read_zip(path: &Path) -> io::Result<(Vec<(PathBuf, EntryLocation)>, ArchiveData)> {
+    let mut archive = zip::ZipArchive::new(open_file(path)?)?;
+    let mut entries = vec![];
+    for index in 0..archive.len() {
+        let file = archive.by_index(index)?;
+        if !file.is_file() {
+            continue;
+        }
+        if let Some(name) = file.enclosed_name() {
+            entries.push((name, EntryLocation::Zip { index }));
+        }
+    }
+    Ok((entries, ArchiveData::Zip(Mutex::new(archive))))
+}
+
+fn decompress_tar(path: &Path) -> io::Result<(Vec<(PathBuf, EntryLocation)>, ArchiveData)> {
+    let mut decompressed = NamedTempFile::new()?;
+    io::copy(&mut GzDecoder::new(open_file(path)?), &mut decompressed)?;
+    let entries = list_tar_entries(decompressed.path())?;
+    Ok((entries, ArchiveData::Decompressed(decompressed)))
+}
+
+fn list_tar_entries(path: &Path) -> io::Result<Vec<(PathBuf, EntryLocation)>> {
+    let mut archive = tar::Archive::new(open_file(path)?);
+    let mut entries = vec![];
+    for entry in archive.entries()? {
+        let entry = entry?;
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+        let Some(name) = enclosed_name(&entry.path()?) else {
+            continue;
+        };
+        entries.push((
+            name,
+            EntryLocation::Tar {
+                offset: entry.raw_file_position(),
+                size: entry.size(),
+            },
+        ));
+    }
+    Ok(entries)
+}
+
+/// Normalizes `./a/b` to `a/b` and rejects names that point outside of the archive.
+fn enclosed_name(name: &Path) -> Option<PathBuf> {
+    name.components()
+        .filter(|c| *c != Component::CurDir)
+        .map(|c| match c {
+            Component::Normal(c) => Some(c),
+            _ => None,
+        })
+        .collect::<Option<PathBuf>>()
+        .filter(|p| p.file_name().is_some())
+}
+
+/// Strips one top-level directory if it contains everything, but not the directories
+/// below it, which are the sections even if there is only one.
+fn strip_common_directory(
+    entries: Vec<(PathBuf, EntryLocation)>,
+) -> BTreeMap<PathBuf, EntryLocation> {
+    let common = entries.first().and_then(|(name, _)| {
+        let first = name.components().next()?;
+        entries
+            .iter()
+            .all(|(name, _)| {
+                name.components().count() > 1 && name.components().next() == Some(first)
+            })
+            .then(|| PathBuf::from(first.as_os_str()))
+    });
+    match common {
+        Some(common) => entries
+            .into_iter()
+            .filter_map(|(name, location)| {
+                Some((name.strip_prefix(&common).ok()?.to_path_buf(), location))
+            })
+            .collect(),
+        None => entries.into_iter().collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+
+    use speculoos::prelude::*;
+    use zip::write::SimpleFileOptions;
+
+    use crate::archive::{Archive, ArchiveKind};
+    use crate::source::{Listing, MinutesSource};
+
+    fn write_zip(path: &Path, files: &[(&str, &[u8])]) -> anyhow::Result<()> {
+        let mut zip = zip::ZipWriter::new(fs_err::File::create(path)?);
+        for (name, content) in files {
+            zip.start_file(*name, SimpleFileOptions::default())?;
+            zip.write_all(content)?;
+        }
+        zip.finish()?;
+        Ok(())
+    }
+
+    fn write_tar(path: &Path, files: &[(&str, &[u8])]) -> anyhow::Result<()> {
+        let mut tar = tar::Builder::new(fs_err::File::create(path)?);
+        for (name, content) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(content.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, name, *content)?;
+        }
+        tar.finish()?;
+        Ok(())
+    }
+
+    #[test]
+    fn archive_kind_is_detected_by_file_name() {
+        assert_that!(ArchiveKind::of(Path::new("/a/photos.ZIP")))
+            .is_equal_to(Some(ArchiveKind::Zip));
+        assert_that!(ArchiveKind::of(Path::new("/a/photos.tar")))
+            .is_equal_to(Some(ArchiveKind::Tar));
+        assert_that!(ArchiveKind::of(Path::new("/a/photos.tar.gz")))
+            .is_equal_to(Some(ArchiveKind::TarGz));
+        assert_that!(ArchiveKind::of(Path::new("/a/photos.tgz")))
+            .is_equal_to(Some(ArchiveKind::TarGz));
+        assert_that!(ArchiveKind::of(Path::new("/a/photos"))).is_none();
+    }
+
+    #[test]
+    fn zip_entries_are_listed_and_read() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("photos.zip");
+        write_zip(
+            &path,
+            &[
+                ("minutes/session 1/1.jpg", b"one"),
+                ("minutes/session 1/2.jpg", b"two"),
+                ("minutes/group.jpg", b"group"),
+            ],
+        )?;
+
+        let archive = Archive::open(&path)?;
+
+        assert_that!(archive.list(Path::new(""))?).is_equal_to(Listing {
+            dirs: vec![PathBuf::from("session 1")],
+            files: vec![PathBuf::from("group.jpg")],
+        });
+        assert_that!(archive.read(Path::new("session 1/2.jpg"))?).is_equal_to(b"two".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn only_one_wrapper_directory_is_stripped() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("photos.zip");
+        write_zip(
+            &path,
+            &[
+                ("minutes/session 1/1.jpg", b"one"),
+                ("minutes/session 1/2.jpg", b"two"),
+            ],
+        )?;
+
+        let archive = Archive::open(&path)?;
+
+        assert_that!(archive.list(Path::new(""))?).is_equal_to(Listing {
+            dirs: vec![PathBuf::from("session 1")],
+            files: vec![],
+        });
+        assert_that!(archive.read(Path::new("session 1/1.jpg"))?).is_equal_to(b"one".to_vec());
+        Ok(())
+    }
+
+    #[test]
+    fn tar_entries_are_read_at_their_offset() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("photos.tar");
+        write_tar(&path, &[("a/1.jpg", b"one"), ("b/2.jpg", b"two")])?;
+
+        let archive = Archive::open(&path)?;
+
+        assert_that!(archive.read(Path::new("a/1.jpg"))?).is_equal_to(b"one".to_vec());
+        assert_that!(archive.read(Path::new("b/2.jpg"))?).is_equal_to(b"two".to_vec());
+        assert_that!(archive.read(Path::new("c/3.jpg"))).is_err();
+        Ok(())
+    }
+
+    #[test]
+    fn compressed_tar_entries_are_read() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let tar_path = dir.path().join("photos.tar");
+        write_tar(&tar_path, &[("./a/1.jpg", b"one")])?;
+        let path = dir.path().join("photos.tgz");
+        let mut encoder =
+            flate2::write::GzEncoder::new(fs_err::File::create(&path)?, Default::default());
+        encoder.write_all(&fs_err::read(&tar_path)?)?;
+        encoder.finish()?;
+
+        let archive = Archive::open(&path)?;
+
+        assert_that!(archive.read(Path::new("1.jpg"))?).is_equal_to(b"one".to_vec());
+        Ok(())
+    }
+}
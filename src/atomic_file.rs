@@ -0,0 +1,169 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::File;
+use std::io::{self, BufWriter};
+use std::path::Path;
+
+use tempfile::NamedTempFile;
+
+use crate::error::Error;
+
+/// Temporary files are hidden and end in this, so that left-overs of a killed run are
+/// recognizable.
+const TEMP_SUFFIX: &str = ".part";
+
+/// Writes `path` through a temporary file in the same directory that is renamed to `path`
+/// once `write` succeeded, so `path` is either complete or missing. Creates the parent
+/// directories and fails if `path` exists already.
+pub fn create_new(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let temp_file = write_temp_file(path, write)?;
+    temp_file
+        .persist_noclobber(path)
+        .map_err(|e| Error::write(path)(e.error))?;
+    Ok(())
+}
+
+/// Like [`create_new`], but replaces `path` if it exists.
+pub fn replace(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), Error>,
+) -> Result<(), Error> {
+    let temp_file = write_temp_file(path, write)?;
+    temp_file
+        .persist(path)
+        .map_err(|e| Error::write(path)(e.error))?;
+    Ok(())
+}
+
+/// Removes temporary files of earlier runs that were killed while writing one of
+/// `paths`. Directories that do not exist are ignored.
+pub fn remove_leftovers<'a>(paths: impl IntoIterator<Item = &'a Path>) -> Result<(), Error> {
+    let mut file_names_by_dir: BTreeMap<&Path, BTreeSet<String>> = BTreeMap::new();
+    for path in paths {
+        if let (Some(dir), Some(file_name)) = (path.parent(), path.file_name()) {
+            file_names_by_dir
+                .entry(dir)
+                .or_default()
+                .insert(file_name.to_string_lossy().into_owned());
+        }
+    }
+    for (dir, file_names) in file_names_by_dir {
+        let entries = match fs_err::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(Error::read(dir)(e)),
+        };
+        for entry in entries {
+            let entry = entry.map_err(Error::read(dir))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            let temp_file_of = name
+                .strip_prefix('.')
+                .and_then(|n| n.strip_suffix(TEMP_SUFFIX))
+                .and_then(|n| n.rsplit_once('.'))
+                .map(|(file_name, _random)| file_name);
+            if temp_file_of.is_some_and(|file_name| file_names.contains(file_name)) {
+                fs_err::remove_file(entry.path()).map_err(Error::write(entry.path()))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The temporary file is removed when it is dropped without being persisted, e.g. if
+/// `write` fails.
+fn write_temp_file(
+    path: &Path,
+    write: impl FnOnce(&mut BufWriter<File>) -> Result<(), Error>,
+) -> Result<NamedTempFile, Error> {
+    let dir = match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    fs_err::create_dir_all(dir).map_err(Error::write(dir))?;
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    let temp_file = tempfile::Builder::new()
+        .prefix(&format!(".{}.", file_name))
+        .suffix(TEMP_SUFFIX)
+        .tempfile_in(dir)
+        .map_err(Error::write(dir))?;
+    let mut writer = BufWriter::new(temp_file.reopen().map_err(Error::write(path))?);
+    write(&mut writer)?;
+    writer
+        .into_inner()
+        .map_err(|e| Error::write(path)(e.into_error()))?
+        .sync_all()
+        .map_err(Error::write(path))?;
+    Ok(temp_file)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use speculoos::prelude::*;
+
+    use crate::atomic_file::{create_new, remove_leftovers, replace};
+    use crate::error::Error;
+
+    #[test]
+    fn failed_writes_leave_no_files_behind() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("sub/a.jpg");
+
+        let result = create_new(&path, |writer| {
+            writer
+                .write_all(b"half an image")
+                .map_err(Error::write(&path))?;
+            Err(Error::InvalidImage {
+                path: path.clone(),
+                message: "broken".to_string(),
+            })
+        });
+
+        assert_that!(result.is_err()).is_true();
+        assert_that!(fs_err::read_dir(dir.path().join("sub"))?.count()).is_equal_to(0);
+        Ok(())
+    }
+
+    #[test]
+    fn only_replace_overwrites_existing_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("minutes.md");
+        fs_err::write(&path, "old")?;
+
+        let result = create_new(&path, |writer| {
+            writer.write_all(b"new").map_err(Error::write(&path))
+        });
+        assert_that!(matches!(result, Err(Error::DestinationExists { .. }))).is_true();
+        replace(&path, |writer| {
+            writer.write_all(b"new").map_err(Error::write(&path))
+        })?;
+
+        assert_that!(fs_err::read_to_string(&path)?).is_equal_to("new".to_string());
+        assert_that!(fs_err::read_dir(dir.path())?.count()).is_equal_to(1);
+        Ok(())
+    }
+
+    #[test]
+    fn leftovers_of_the_given_files_are_removed() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs_err::write(dir.path().join(".a.jpg.x7Yq2b.part"), "half")?;
+        fs_err::write(dir.path().join(".b.jpg.x7Yq2b.part"), "unrelated")?;
+        fs_err::write(dir.path().join("a.jpg"), "complete")?;
+
+        remove_leftovers([
+            dir.path().join("a.jpg").as_path(),
+            dir.path().join("missing/c.jpg").as_path(),
+        ])?;
+
+        let mut names: Vec<_> = fs_err::read_dir(dir.path())?
+            .map(|e| Ok(e?.file_name().to_string_lossy().into_owned()))
+            .collect::<anyhow::Result<_>>()?;
+        names.sort();
+        assert_that!(names)
+            .is_equal_to(vec![".b.jpg.x7Yq2b.part".to_string(), "a.jpg".to_string()]);
+        Ok(())
+    }
+}
@@ -0,0 +1,64 @@
+use chrono::NaiveDateTime;
+use exif::{In, Tag, Value};
+
+use crate::error::Error;
+use crate::images::SourceImagePath;
+
+const EXIF_DATE_TIME_FORMAT: &str = "%Y:%m:%d %H:%M:%S";
+
+/// Reads when the photo was taken from the EXIF `DateTimeOriginal` tag, falling back
+/// to `DateTime`. Images without (valid) EXIF data have no capture time.
+pub fn read_capture_time(image: &SourceImagePath) -> Result<Option<NaiveDateTime>, Error> {
+    let mut reader = image.open()?;
+    let exif = match exif::Reader::new().read_from_container(&mut reader) {
+        Ok(exif) => exif,
+        Err(_) => return Ok(None),
+    };
+
+    Ok([Tag::DateTimeOriginal, Tag::DateTime]
+        .into_iter()
+        .filter_map(|tag| exif.get_field(tag, In::PRIMARY))
+        .find_map(|field| match &field.value {
+            Value::Ascii(values) => values
+                .first()
+                .and_then(|v| parse_exif_date_time(&String::from_utf8_lossy(v))),
+            _ => None,
+        }))
+}
+
+fn parse_exif_date_time(value: &str) -> Option<NaiveDateTime> {
+    NaiveDateTime::parse_from_str(value.trim(), EXIF_DATE_TIME_FORMAT).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::NaiveDate;
+    use speculoos::prelude::*;
+
+    use crate::capture_time::{parse_exif_date_time, read_capture_time};
+    use crate::images::SourceImagePath;
+
+    #[test]
+    fn parses_exif_date_time_format() {
+        let expected = NaiveDate::from_ymd_opt(2024, 5, 17)
+            .and_then(|d| d.and_hms_opt(9, 30, 5))
+            .unwrap();
+
+        assert_that!(parse_exif_date_time("2024:05:17 09:30:05"))
+            .is_some()
+            .is_equal_to(expected);
+        assert_that!(parse_exif_date_time("    :  :     :  :  ")).is_none();
+    }
+
+    #[test]
+    fn image_without_exif_has_no_capture_time() -> anyhow::Result<()> {
+        let capture_time = read_capture_time(&SourceImagePath::new(PathBuf::from(
+            "./src/empty-100x200.jpg",
+        )))?;
+
+        assert_that!(capture_time).is_none();
+        Ok(())
+    }
+}
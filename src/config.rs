@@ -0,0 +1,599 @@
+use std::fmt::{Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use chrono::Duration;
+use serde::{Deserialize, Serialize};
+
+use crate::conversion::OutputLayout;
+use crate::error::Error;
+use crate::grouping::{Grouping, Schedule};
+use crate::image_operations::{ResamplingFilter, ResizeOptions, Sizing, Variant};
+use crate::input::{InputOptions, Minutes};
+use crate::naming::NamingScheme;
+use crate::plan::ExistingFiles;
+use crate::publish::PublishTo;
+
+pub const CONFIG_FILE_NAME: &str = "minutes.toml";
+
+/// Prefix of the environment variables that correspond to the configuration keys,
+/// e.g. `MINUTES_THUMBNAIL_RATIO` for `thumbnail-ratio`.
+pub const ENV_PREFIX: &str = "MINUTES_";
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum OutputFormat {
+    Markdown,
+    Html,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "html" => Ok(OutputFormat::Html),
+            other => Err(format!(
+                "unknown format {}, expected markdown or html",
+                other
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for OutputFormat {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for OutputFormat {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            OutputFormat::Markdown => "markdown",
+            OutputFormat::Html => "html",
+        })
+    }
+}
+
+impl From<OutputFormat> for String {
+    fn from(value: OutputFormat) -> Self {
+        value.to_string()
+    }
+}
+
+/// One layer of settings (command line, environment, config file or defaults).
+/// Keys that are not set fall back to the next layer.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone, Default)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct Config {
+    pub input: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub online_base_path: Option<String>,
+    pub thumbnail_ratio: Option<f32>,
+    pub thumbnail_filter: Option<ResamplingFilter>,
+    pub large_filter: Option<ResamplingFilter>,
+    pub variants: Option<Vec<Variant>>,
+    pub thumbnail_variant: Option<String>,
+    pub link_variant: Option<String>,
+    pub format: Option<OutputFormat>,
+    pub sizes: Option<String>,
+    /// Markdown shows the thumbnails with HTML `<img>` tags with their dimensions.
+    pub html_images: Option<bool>,
+    /// File the document is written to instead of stdout.
+    pub document: Option<PathBuf>,
+    /// File a JSON description of the minutes is written to.
+    pub manifest: Option<PathBuf>,
+    pub naming: Option<NamingScheme>,
+    pub existing: Option<ExistingFiles>,
+    pub introduction_title: Option<String>,
+    pub group_by_gap: Option<u32>,
+    pub schedule: Option<PathBuf>,
+    /// Where `build` and `publish` upload the output directory to.
+    pub publish_to: Option<PublishTo>,
+}
+
+/// Where the minutes are read from and how they are split into sections.
+#[derive(Clone, Debug)]
+pub struct InputSettings {
+    pub root_path: PathBuf,
+    pub introduction_title: String,
+    pub grouping: Option<Grouping>,
+}
+
+impl InputSettings {
+    pub fn read_minutes(&self) -> Result<Minutes, Error> {
+        match &self.grouping {
+            Some(grouping) => Minutes::read_flat(&self.root_path, grouping),
+            None => Minutes::read(
+                &self.root_path,
+                &InputOptions {
+                    introduction_title: self.introduction_title.clone(),
+                },
+            ),
+        }
+    }
+}
+
+/// How the document is written and refers to the generated images.
+#[derive(Clone, Debug)]
+pub struct DocumentSettings {
+    pub online_base_path: String,
+    pub thumbnail_variant: String,
+    pub link_variant: String,
+    pub format: OutputFormat,
+    pub sizes: String,
+    pub html_images: bool,
+    pub path: Option<PathBuf>,
+    pub manifest: Option<PathBuf>,
+}
+
+impl Config {
+    /// Reads a config file. Relative paths in it are relative to the directory of the file.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let content = fs_err::read_to_string(path).map_err(Error::read(path))?;
+        let config = Self::parse(&content).map_err(|e| e.in_file(path))?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        Ok(Self {
+            input: config.input.map(|p| dir.join(p)),
+            output: config.output.map(|p| dir.join(p)),
+            schedule: config.schedule.map(|p| dir.join(p)),
+            document: config.document.map(|p| dir.join(p)),
+            manifest: config.manifest.map(|p| dir.join(p)),
+            publish_to: config.publish_to.map(|to| match to {
+                PublishTo::Directory(p) => PublishTo::Directory(dir.join(p)),
+                other => other,
+            }),
+            ..config
+        })
+    }
+
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        toml::from_str(content).map_err(Error::parse)
+    }
+
+    /// Reads the `MINUTES_*` variables with the given lookup, e.g. `std::env::var`.
+    /// `MINUTES_VARIANTS` separates the variants by commas.
+    pub fn from_env<E>(lookup: impl Fn(&str) -> Result<String, E>) -> Result<Self, Error> {
+        let variants: Option<String> = env_value(&lookup, "variants")?;
+        Ok(Self {
+            input: env_value(&lookup, "input")?,
+            output: env_value(&lookup, "output")?,
+            online_base_path: env_value(&lookup, "online-base-path")?,
+            thumbnail_ratio: env_value(&lookup, "thumbnail-ratio")?,
+            thumbnail_filter: env_value(&lookup, "thumbnail-filter")?,
+            large_filter: env_value(&lookup, "large-filter")?,
+            variants: variants
+                .map(|v| {
+                    v.split(',')
+                        .map(|variant| parse_env_value(variant.trim(), "variants"))
+                        .collect::<Result<_, Error>>()
+                })
+                .transpose()?,
+            thumbnail_variant: env_value(&lookup, "thumbnail-variant")?,
+            link_variant: env_value(&lookup, "link-variant")?,
+            format: env_value(&lookup, "format")?,
+            sizes: env_value(&lookup, "sizes")?,
+            html_images: env_value(&lookup, "html-images")?,
+            document: env_value(&lookup, "document")?,
+            manifest: env_value(&lookup, "manifest")?,
+            naming: env_value(&lookup, "naming")?,
+            existing: env_value(&lookup, "existing")?,
+            introduction_title: env_value(&lookup, "introduction-title")?,
+            group_by_gap: env_value(&lookup, "group-by-gap")?,
+            schedule: env_value(&lookup, "schedule")?,
+            publish_to: env_value(&lookup, "publish-to")?,
+        })
+    }
+
+    /// Values used when no layer sets a key.
+    pub fn defaults() -> Self {
+        Self {
+            thumbnail_filter: Some(ResamplingFilter::default()),
+            large_filter: Some(ResamplingFilter::default()),
+            thumbnail_variant: Some("small".to_string()),
+            link_variant: Some("large".to_string()),
+            format: Some(OutputFormat::Markdown),
+            sizes: Some("100vw".to_string()),
+            naming: Some(NamingScheme::default()),
+            existing: Some(ExistingFiles::default()),
+            introduction_title: Some(InputOptions::default().introduction_title),
+            ..Self::default()
+        }
+    }
+
+    /// Takes the keys that are not set in `self` from `fallback`. The ways to select
+    /// the variants (`thumbnail-ratio` or `variants`) and the grouping (`group-by-gap`
+    /// or `schedule`) replace each other, so a layer that sets one of them hides both
+    /// of the fallback.
+    pub fn or(self, fallback: Config) -> Config {
+        let (thumbnail_ratio, variants) =
+            if self.thumbnail_ratio.is_some() || self.variants.is_some() {
+                (self.thumbnail_ratio, self.variants)
+            } else {
+                (fallback.thumbnail_ratio, fallback.variants)
+            };
+        let (group_by_gap, schedule) = if self.group_by_gap.is_some() || self.schedule.is_some() {
+            (self.group_by_gap, self.schedule)
+        } else {
+            (fallback.group_by_gap, fallback.schedule)
+        };
+        Config {
+            input: self.input.or(fallback.input),
+            output: self.output.or(fallback.output),
+            online_base_path: self.online_base_path.or(fallback.online_base_path),
+            thumbnail_ratio,
+            thumbnail_filter: self.thumbnail_filter.or(fallback.thumbnail_filter),
+            large_filter: self.large_filter.or(fallback.large_filter),
+            variants,
+            thumbnail_variant: self.thumbnail_variant.or(fallback.thumbnail_variant),
+            link_variant: self.link_variant.or(fallback.link_variant),
+            format: self.format.or(fallback.format),
+            sizes: self.sizes.or(fallback.sizes),
+            html_images: self.html_images.or(fallback.html_images),
+            document: self.document.or(fallback.document),
+            manifest: self.manifest.or(fallback.manifest),
+            naming: self.naming.or(fallback.naming),
+            existing: self.existing.or(fallback.existing),
+            introduction_title: self.introduction_title.or(fallback.introduction_title),
+            group_by_gap,
+            schedule,
+            publish_to: self.publish_to.or(fallback.publish_to),
+        }
+    }
+
+    /// Layers command line, environment, config file and defaults. The config file is
+    /// `config_file` if given and otherwise `minutes.toml` in the input directory, if any.
+    pub fn load<E>(
+        cli: Config,
+        config_file: Option<&Path>,
+        env: impl Fn(&str) -> Result<String, E>,
+    ) -> Result<Self, Error> {
+        let env = Self::from_env(env)?;
+        let config_file = match config_file {
+            Some(path) => Some(path.to_path_buf()),
+            None => cli
+                .input
+                .as_ref()
+                .or(env.input.as_ref())
+                .map(|input| input.join(CONFIG_FILE_NAME))
+                .filter(|path| path.is_file()),
+        };
+        let file = match config_file {
+            Some(path) => Self::read(&path)?,
+            None => Self::default(),
+        };
+        Ok(cli.or(env).or(file).or(Self::defaults()))
+    }
+
+    pub fn to_toml(&self) -> Result<String, Error> {
+        toml::to_string(self).map_err(|e| Error::InvalidConfig(e.to_string()))
+    }
+
+    pub fn input_settings(&self) -> Result<InputSettings, Error> {
+        let grouping = match (self.group_by_gap, &self.schedule) {
+            (Some(_), Some(_)) => {
+                return Err(Error::InvalidConfig(
+                    "either `group-by-gap` or `schedule` can be given, not both".to_string(),
+                ))
+            }
+            (Some(minutes), None) => Some(Grouping::Gap(Duration::minutes(minutes.into()))),
+            (None, Some(schedule)) => Some(Grouping::Schedule(Schedule::read(schedule)?)),
+            (None, None) => None,
+        };
+        Ok(InputSettings {
+            root_path: self.input.clone().ok_or_else(|| missing("INPUT"))?,
+            introduction_title: self
+                .introduction_title
+                .clone()
+                .unwrap_or_else(|| InputOptions::default().introduction_title),
+            grouping,
+        })
+    }
+
+    /// The generated variants, either given directly or as `small` and `large` derived
+    /// from the thumbnail ratio.
+    pub fn variants(&self) -> Result<Vec<Variant>, Error> {
+        let thumbnail_filter = self.thumbnail_filter.unwrap_or_default();
+        let large_filter = self.large_filter.unwrap_or_default();
+        Ok(match (self.thumbnail_ratio, &self.variants) {
+            (Some(_), Some(_)) => {
+                return Err(Error::InvalidConfig(
+                    "either `thumbnail-ratio` or `variants` can be given, not both".to_string(),
+                ))
+            }
+            (Some(thumbnail_ratio), None) if !(thumbnail_ratio > 0.0 && thumbnail_ratio <= 1.0) => {
+                return Err(Error::InvalidConfig(format!(
+                    "invalid `thumbnail-ratio` {}, expected a ratio greater than 0 and at most 1",
+                    thumbnail_ratio
+                )))
+            }
+            (Some(thumbnail_ratio), None) => vec![
+                Variant {
+                    name: "small".to_string(),
+                    resize: ResizeOptions {
+                        sizing: Sizing::Ratio(thumbnail_ratio),
+                        filter: thumbnail_filter,
+                    },
+                },
+                Variant {
+                    name: "large".to_string(),
+                    resize: ResizeOptions {
+                        sizing: Sizing::Ratio(1.0),
+                        filter: large_filter,
+                    },
+                },
+            ],
+            (None, Some(variants)) if !variants.is_empty() => variants.clone(),
+            _ => {
+                return Err(Error::InvalidConfig(
+                    "expected `--thumbnail-ratio=THUMBNAIL_RATIO` or at least one `--variant`"
+                        .to_string(),
+                ))
+            }
+        })
+    }
+
+    pub fn output_root(&self) -> Result<PathBuf, Error> {
+        self.output.clone().ok_or_else(|| missing("OUTPUT"))
+    }
+
+    pub fn online_base_path(&self) -> Result<String, Error> {
+        self.online_base_path
+            .clone()
+            .ok_or_else(|| missing("ONLINE_BASE_PATH"))
+    }
+
+    pub fn output_layout(&self) -> Result<OutputLayout, Error> {
+        Ok(OutputLayout {
+            output_root: self.output_root()?,
+            variants: self.variants()?,
+            naming: self.naming.clone().unwrap_or_default(),
+        })
+    }
+
+    /// The thumbnail and link variants have to be among the generated `variants`.
+    pub fn document_settings(&self, variants: &[Variant]) -> Result<DocumentSettings, Error> {
+        let thumbnail_variant = self
+            .thumbnail_variant
+            .clone()
+            .unwrap_or_else(|| "small".to_string());
+        let link_variant = self
+            .link_variant
+            .clone()
+            .unwrap_or_else(|| "large".to_string());
+        if ![&thumbnail_variant, &link_variant]
+            .iter()
+            .all(|name| variants.iter().any(|v| &&v.name == name))
+        {
+            return Err(Error::InvalidConfig(
+                "the thumbnail and link variants must be among the generated variants".to_string(),
+            ));
+        }
+        Ok(DocumentSettings {
+            online_base_path: self.online_base_path()?,
+            thumbnail_variant,
+            link_variant,
+            format: self.format.unwrap_or(OutputFormat::Markdown),
+            sizes: self.sizes.clone().unwrap_or_else(|| "100vw".to_string()),
+            html_images: self.html_images.unwrap_or(false),
+            path: self.document.clone(),
+            manifest: self.manifest.clone(),
+        })
+    }
+}
+
+fn missing(key: &str) -> Error {
+    Error::InvalidConfig(format!(
+        "no {} given on the command line, in the environment or in {}",
+        key, CONFIG_FILE_NAME
+    ))
+}
+
+fn env_name(key: &str) -> String {
+    format!("{}{}", ENV_PREFIX, key.to_uppercase().replace('-', "_"))
+}
+
+fn env_value<T: FromStr, E>(
+    lookup: &impl Fn(&str) -> Result<String, E>,
+    key: &str,
+) -> Result<Option<T>, Error>
+where
+    T::Err: Display,
+{
+    lookup(&env_name(key))
+        .ok()
+        .map(|value| parse_env_value(&value, key))
+        .transpose()
+}
+
+fn parse_env_value<T: FromStr>(value: &str, key: &str) -> Result<T, Error>
+where
+    T::Err: Display,
+{
+    value.parse().map_err(|e| {
+        Error::InvalidConfig(format!(
+            "invalid value {} of {}: {}",
+            value,
+            env_name(key),
+            e
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+
+    use speculoos::prelude::*;
+
+    use crate::config::{Config, OutputFormat, CONFIG_FILE_NAME};
+    use crate::image_operations::{ResamplingFilter, Sizing};
+    use crate::publish::PublishTo;
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Result<String, ()> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        move |key| vars.get(key).cloned().ok_or(())
+    }
+
+    #[test]
+    fn config_file_covers_all_options() -> anyhow::Result<()> {
+        let config = Config::parse(
+            r#"
+            output = "out"
+            online-base-path = "https://example.org/minutes"
+            variants = ["thumb=320w", "large=original:lanczos3"]
+            thumbnail-variant = "thumb"
+            format = "html"
+            document = "minutes.html"
+            naming = "{section_slug}/{index:03}_{variant}.{ext_lower}"
+            group-by-gap = 30
+            "#,
+        )?;
+
+        assert_that!(config.variants.as_ref().map(|v| v[0].resize.sizing))
+            .is_equal_to(Some(Sizing::MaxWidth(320)));
+        assert_that!(config.format).is_equal_to(Some(OutputFormat::Html));
+        assert_that!(config.document).is_equal_to(Some(PathBuf::from("minutes.html")));
+        assert_that!(config.group_by_gap).is_equal_to(Some(30));
+        assert_that!(Config::parse("thumbnail-ration = 0.3")).is_err();
+        Ok(())
+    }
+
+    #[test]
+    fn relative_paths_in_config_files_are_relative_to_the_file() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join(CONFIG_FILE_NAME);
+        fs_err::write(&path, "output = \"out\"\npublish-to = \"www\"\n")?;
+
+        let config = Config::read(&path)?;
+
+        assert_that!(config.output).is_equal_to(Some(dir.path().join("out")));
+        assert_that!(config.publish_to)
+            .is_equal_to(Some(PublishTo::Directory(dir.path().join("www"))));
+        Ok(())
+    }
+
+    #[test]
+    fn command_line_wins_over_environment_over_config_file_over_defaults() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs_err::write(
+            dir.path().join(CONFIG_FILE_NAME),
+            "thumbnail-ratio = 0.1\nsizes = \"50vw\"\nformat = \"html\"\nonline-base-path = \"/file\"\n",
+        )?;
+        let cli = Config {
+            input: Some(dir.path().to_path_buf()),
+            online_base_path: Some("/cli".to_string()),
+            ..Config::default()
+        };
+
+        let config = Config::load(
+            cli,
+            None,
+            env(&[
+                ("MINUTES_SIZES", "80vw"),
+                ("MINUTES_ONLINE_BASE_PATH", "/env"),
+            ]),
+        )?;
+
+        assert_that!(config.online_base_path).is_equal_to(Some("/cli".to_string()));
+        assert_that!(config.sizes).is_equal_to(Some("80vw".to_string()));
+        assert_that!(config.format).is_equal_to(Some(OutputFormat::Html));
+        assert_that!(config.thumbnail_ratio).is_equal_to(Some(0.1));
+        assert_that!(config.thumbnail_filter).is_equal_to(Some(ResamplingFilter::Triangle));
+        Ok(())
+    }
+
+    #[test]
+    fn variants_of_a_higher_layer_replace_the_thumbnail_ratio() -> anyhow::Result<()> {
+        let cli = Config {
+            variants: Some(vec![
+                "small=320w".parse().unwrap(),
+                "large=original".parse().unwrap(),
+            ]),
+            ..Config::default()
+        };
+        let file = Config {
+            thumbnail_ratio: Some(0.3),
+            input: Some(PathBuf::from("/in")),
+            output: Some(PathBuf::from("/out")),
+            online_base_path: Some("/".to_string()),
+            ..Config::default()
+        };
+
+        let variants = cli.or(file).or(Config::defaults()).variants()?;
+
+        assert_that!(variants[0].resize.sizing).is_equal_to(Sizing::MaxWidth(320));
+        Ok(())
+    }
+
+    #[test]
+    fn environment_variables_are_parsed() -> anyhow::Result<()> {
+        let config = Config::from_env(env(&[
+            ("MINUTES_VARIANTS", "thumb=320w, large=original"),
+            ("MINUTES_GROUP_BY_GAP", "45"),
+        ]))?;
+
+        assert_that!(config.variants.map(|v| v.len())).is_equal_to(Some(2));
+        assert_that!(config.group_by_gap).is_equal_to(Some(45));
+        let result = Config::from_env(env(&[("MINUTES_THUMBNAIL_RATIO", "much")]));
+        let err = assert_that!(result).is_err().subject.to_string();
+        assert_that!(err).contains("MINUTES_THUMBNAIL_RATIO");
+        Ok(())
+    }
+
+    #[test]
+    fn thumbnail_ratios_outside_of_the_image_are_rejected() -> anyhow::Result<()> {
+        for thumbnail_ratio in [0.0, -0.5, 1.5, f32::NAN] {
+            let config = Config {
+                thumbnail_ratio: Some(thumbnail_ratio),
+                ..Config::defaults()
+            };
+
+            let result = config.variants();
+
+            let err = assert_that!(result).is_err().subject.to_string();
+            assert_that!(err).contains("invalid `thumbnail-ratio`");
+        }
+        let config = Config {
+            thumbnail_ratio: Some(1.0),
+            ..Config::defaults()
+        };
+        assert_that!(config.variants()?).has_length(2);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_required_keys_are_reported() {
+        let config = Config {
+            thumbnail_ratio: Some(0.3),
+            ..Config::defaults()
+        };
+
+        let result = config.output_layout();
+
+        let err = assert_that!(result).is_err().subject.to_string();
+        assert_that!(err).contains("no OUTPUT given");
+    }
+
+    #[test]
+    fn printed_config_can_be_read_again() -> anyhow::Result<()> {
+        let config = Config {
+            thumbnail_ratio: Some(0.3),
+            ..Config::defaults()
+        };
+
+        let printed = config.to_toml()?;
+
+        assert_that!(printed).contains("thumbnail-ratio = 0.3");
+        assert_that!(Config::parse(&printed)?).is_equal_to(config);
+        Ok(())
+    }
+}
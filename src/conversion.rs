@@ -1,24 +1,47 @@
+use std::collections::{BTreeMap, HashMap};
 use std::path::{Path, PathBuf};
 
+use crate::error::Error;
+use crate::image_operations::{generated_dimensions, Variant};
 use crate::images::SourceImagePath;
 use crate::input::{Minutes, Section};
+use crate::naming::{ImageNamingContext, NamingScheme};
+use crate::overrides::ImageOverrides;
+
+/// Where and under which names the generated images are stored.
+#[derive(PartialEq, Debug, Clone)]
+pub struct OutputLayout {
+    pub output_root: PathBuf,
+    pub variants: Vec<Variant>,
+    pub naming: NamingScheme,
+}
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct OutputImageFilesForConversion<'source> {
-    pub source_image_path: &'source Path,
-    pub small_image: PathBuf,
-    pub large_image: PathBuf,
+    pub source_image_path: &'source SourceImagePath,
+    pub overrides: ImageOverrides,
+    pub variants: BTreeMap<String, PathBuf>,
+    /// Width and height of the generated files by variant name, once known.
+    pub dimensions: BTreeMap<String, (u32, u32)>,
 }
 
 impl<'source> OutputImageFilesForConversion<'source> {
     fn try_from_image_path(
         source_image_path: &'source SourceImagePath,
-        output_base_path: &Path,
-    ) -> anyhow::Result<Self> {
-        anyhow::Ok(Self {
-            source_image_path: source_image_path.path(),
-            large_image: source_image_path.large_image_path(output_base_path)?,
-            small_image: source_image_path.small_image_path(output_base_path)?,
+        context: &ImageNamingContext,
+        overrides: ImageOverrides,
+        layout: &OutputLayout,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            source_image_path,
+            overrides,
+            variants: layout
+                .naming
+                .output_paths(context, &layout.variants)?
+                .into_iter()
+                .map(|(name, path)| (name, layout.output_root.join(path)))
+                .collect(),
+            dimensions: BTreeMap::new(),
         })
     }
 }
@@ -27,63 +50,354 @@ impl<'source> OutputImageFilesForConversion<'source> {
 pub struct SectionForConversion<'source> {
     pub name: &'source str,
     pub image_files: Vec<OutputImageFilesForConversion<'source>>,
+    pub subsections: Vec<SectionForConversion<'source>>,
 }
 
 impl<'source> SectionForConversion<'source> {
+    /// `parent_names` are the names of the enclosing sections, outermost first.
     pub fn try_from_section(
         section: &'source Section,
-        output_base_path: &Path,
-    ) -> anyhow::Result<Self> {
-        anyhow::Ok(SectionForConversion {
+        parent_names: &[&'source str],
+        layout: &OutputLayout,
+    ) -> Result<Self, Error> {
+        let mut section_path = parent_names.to_vec();
+        section_path.push(&section.name);
+        Ok(SectionForConversion {
             name: section.name.as_ref(),
             image_files: section
                 .image_files
                 .iter()
-                .map(|i| OutputImageFilesForConversion::try_from_image_path(i, output_base_path))
-                .collect::<anyhow::Result<_>>()?,
+                .enumerate()
+                .map(|(index, i)| {
+                    OutputImageFilesForConversion::try_from_image_path(
+                        i,
+                        &ImageNamingContext {
+                            section_path: &section_path,
+                            index: index + 1,
+                            source: i,
+                        },
+                        section.overrides.for_image(i),
+                        layout,
+                    )
+                    .map_err(|e| e.in_section(&section.name))
+                })
+                .collect::<Result<_, Error>>()?,
+            subsections: section
+                .subsections
+                .iter()
+                .map(|s| SectionForConversion::try_from_section(s, &section_path, layout))
+                .collect::<Result<_, Error>>()?,
         })
     }
+
+    fn collect_image_files<'a>(
+        &'a self,
+        image_files: &mut Vec<&'a OutputImageFilesForConversion<'source>>,
+    ) {
+        image_files.extend(&self.image_files);
+        self.subsections
+            .iter()
+            .for_each(|s| s.collect_image_files(image_files));
+    }
+
+    fn collect_image_files_by_section<'a>(
+        &'a self,
+        parent_names: &[&'source str],
+        image_files: &mut Vec<ImageInSection<'a, 'source>>,
+    ) {
+        let mut section_path = parent_names.to_vec();
+        section_path.push(self.name);
+        image_files.extend(self.image_files.iter().map(|f| (section_path.clone(), f)));
+        self.subsections
+            .iter()
+            .for_each(|s| s.collect_image_files_by_section(&section_path, image_files));
+    }
+
+    fn retain_images(&mut self, keep: &impl Fn(&OutputImageFilesForConversion) -> bool) {
+        self.image_files.retain(|f| keep(f));
+        self.subsections
+            .iter_mut()
+            .for_each(|s| s.retain_images(keep));
+    }
+
+    fn record_dimensions(&mut self, dimensions: &BTreeMap<PathBuf, (u32, u32)>) {
+        for image_file in &mut self.image_files {
+            for (name, path) in &image_file.variants {
+                if let Some(d) = dimensions.get(path) {
+                    image_file.dimensions.insert(name.clone(), *d);
+                }
+            }
+        }
+        self.subsections
+            .iter_mut()
+            .for_each(|s| s.record_dimensions(dimensions));
+    }
 }
 
+/// An image together with the names of its section and the enclosing ones, outermost
+/// first.
+pub type ImageInSection<'a, 'source> = (
+    Vec<&'source str>,
+    &'a OutputImageFilesForConversion<'source>,
+);
+
 #[derive(Debug)]
 pub struct MinutesForConversion<'source> {
+    pub output_root: PathBuf,
     pub sections: Vec<SectionForConversion<'source>>,
 }
 
+/// Several images that would be written to the same output file.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct NameCollision {
+    pub output_path: PathBuf,
+    pub source_image_paths: Vec<PathBuf>,
+}
+
+impl<'source> MinutesForConversion<'source> {
+    /// All images of all sections including nested ones, in document order.
+    pub fn image_files(&self) -> Vec<&OutputImageFilesForConversion<'source>> {
+        let mut image_files = vec![];
+        self.sections
+            .iter()
+            .for_each(|s| s.collect_image_files(&mut image_files));
+        image_files
+    }
+
+    /// Like [`Self::image_files`], but with the section path of each image.
+    pub fn image_files_by_section(&self) -> Vec<ImageInSection<'_, 'source>> {
+        let mut image_files = vec![];
+        self.sections
+            .iter()
+            .for_each(|s| s.collect_image_files_by_section(&[], &mut image_files));
+        image_files
+    }
+
+    /// Removes the images for which `keep` returns false from all sections, e.g. those
+    /// that could not be converted. Sections are kept even if they become empty.
+    pub fn retain_images(&mut self, keep: impl Fn(&OutputImageFilesForConversion) -> bool) {
+        self.sections
+            .iter_mut()
+            .for_each(|s| s.retain_images(&keep));
+    }
+
+    /// Sets the dimensions of the generated files, e.g. from
+    /// [`crate::plan::ConversionReport::dimensions`] or [`Self::read_dimensions`].
+    pub fn record_dimensions(&mut self, dimensions: &BTreeMap<PathBuf, (u32, u32)>) {
+        self.sections
+            .iter_mut()
+            .for_each(|s| s.record_dimensions(dimensions));
+    }
+
+    /// Reads the dimensions of all generated files, which have to exist.
+    pub fn read_dimensions(&self) -> Result<BTreeMap<PathBuf, (u32, u32)>, Error> {
+        self.image_files()
+            .into_iter()
+            .flat_map(|f| f.variants.values())
+            .map(|path| Ok((path.clone(), generated_dimensions(path)?)))
+            .collect()
+    }
+
+    pub fn find_name_collisions(&self) -> Vec<NameCollision> {
+        let mut sources_by_output: HashMap<&Path, Vec<PathBuf>> = HashMap::new();
+        self.image_files()
+            .into_iter()
+            .flat_map(|f| f.variants.values().map(|o| (o, f.source_image_path)))
+            .for_each(|(output_path, source_image_path)| {
+                sources_by_output
+                    .entry(output_path)
+                    .or_default()
+                    .push(source_image_path.path().to_path_buf())
+            });
+        let mut collisions: Vec<_> = sources_by_output
+            .into_iter()
+            .filter(|(_, sources)| sources.len() > 1)
+            .map(|(output_path, source_image_paths)| NameCollision {
+                output_path: output_path.to_path_buf(),
+                source_image_paths,
+            })
+            .collect();
+        collisions.sort_by(|a, b| a.output_path.cmp(&b.output_path));
+        collisions
+    }
+}
+
 pub fn create_minutes_for_conversion<'source>(
     minutes: &'source Minutes,
-    output_base_path: &Path,
-) -> anyhow::Result<MinutesForConversion<'source>> {
+    layout: &OutputLayout,
+) -> Result<MinutesForConversion<'source>, Error> {
     let sections: Vec<_> = minutes
         .sections
         .iter()
-        .map(|s| SectionForConversion::try_from_section(s, output_base_path))
-        .collect::<anyhow::Result<_>>()?;
-    anyhow::Ok(MinutesForConversion { sections })
+        .map(|s| SectionForConversion::try_from_section(s, &[], layout))
+        .collect::<Result<_, Error>>()?;
+    let for_conversion = MinutesForConversion {
+        output_root: layout.output_root.clone(),
+        sections,
+    };
+    if let Some(collision) = for_conversion.find_name_collisions().first() {
+        return Err(Error::NameCollision {
+            output_path: collision.output_path.clone(),
+            source_image_paths: collision.source_image_paths.clone(),
+        });
+    }
+    Ok(for_conversion)
+}
+
+/// Fixtures for the tests that convert images.
+#[cfg(test)]
+pub(crate) mod test_support {
+    use std::path::Path;
+
+    use crate::conversion::{create_minutes_for_conversion, OutputLayout};
+    use crate::image_operations::Variant;
+    use crate::input::{InputOptions, Minutes};
+    use crate::markdown_output::{create_markdown, MarkdownOptions};
+    use crate::naming::NamingScheme;
+    use crate::output::create_minutes_for_output;
+    use crate::plan::{ConversionPlan, ExistingFiles};
+
+    /// A layout with variants like `small=0.5`.
+    pub fn layout(output_root: &Path, variants: &[&str]) -> anyhow::Result<OutputLayout> {
+        Ok(OutputLayout {
+            output_root: output_root.to_path_buf(),
+            variants: variants
+                .iter()
+                .map(|v| v.parse::<Variant>().map_err(anyhow::Error::msg))
+                .collect::<anyhow::Result<_>>()?,
+            naming: NamingScheme::default(),
+        })
+    }
+
+    /// Copies the 100x200 test image to each of `paths` below `input`.
+    pub fn copy_test_images(input: &Path, paths: &[&str]) -> anyhow::Result<()> {
+        for path in paths {
+            let path = input.join(path);
+            if let Some(parent) = path.parent() {
+                fs_err::create_dir_all(parent)?;
+            }
+            fs_err::copy("src/empty-100x200.jpg", path)?;
+        }
+        Ok(())
+    }
+
+    /// Converts the images of `input` and returns the Markdown document that links to
+    /// them below `online_base_path`.
+    pub fn build_markdown(
+        input: &Path,
+        layout: &OutputLayout,
+        online_base_path: &str,
+    ) -> anyhow::Result<String> {
+        let minutes = Minutes::read(input, &InputOptions::default())?;
+        let for_conversion = create_minutes_for_conversion(&minutes, layout)?;
+        ConversionPlan::create(&for_conversion, layout, ExistingFiles::Fail)
+            .execute(false, |_| {})?;
+        let for_output = create_minutes_for_output(&for_conversion, online_base_path)?;
+        Ok(create_markdown(for_output, &MarkdownOptions::default())?)
+    }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::BTreeMap;
     use std::path::{Path, PathBuf};
 
     use speculoos::prelude::*;
 
-    use crate::conversion::OutputImageFilesForConversion;
+    use crate::conversion::{
+        create_minutes_for_conversion, OutputImageFilesForConversion, OutputLayout,
+    };
+    use crate::images::SourceImagePath;
+    use crate::input::{Minutes, Section};
+    use crate::naming::NamingScheme;
+    use crate::overrides::{ImageOverrides, SectionOverrides};
     use crate::OutputImageFiles;
 
+    fn minutes_with_images(image_paths: &[&str]) -> Minutes {
+        Minutes {
+            sections: vec![Section {
+                name: "section-1".to_string(),
+                image_files: image_paths
+                    .iter()
+                    .map(|p| SourceImagePath::new(PathBuf::from(p)))
+                    .collect(),
+                overrides: SectionOverrides::default(),
+                subsections: vec![],
+            }],
+            ignored_files: vec![],
+        }
+    }
+
     #[test]
     fn create_output_images() {
         let source = OutputImageFilesForConversion {
-            source_image_path: Path::new("/home/images/source/file"),
-            large_image: PathBuf::from("/home/images/a/large_file"),
-            small_image: PathBuf::from("/home/images/a/small_file"),
+            source_image_path: &SourceImagePath::new(PathBuf::from("/home/images/source/file")),
+            overrides: ImageOverrides::default(),
+            variants: BTreeMap::from([
+                (
+                    "large".to_string(),
+                    PathBuf::from("/home/images/a/large_file"),
+                ),
+                (
+                    "small".to_string(),
+                    PathBuf::from("/home/images/a/small_file"),
+                ),
+            ]),
+            dimensions: BTreeMap::from([("small".to_string(), (30, 20))]),
         };
         let online_base_path = "http://localhost/documents";
-        let files = OutputImageFiles::create(&source, online_base_path).unwrap();
+        let files =
+            OutputImageFiles::create(&source, Path::new("/home/images"), online_base_path).unwrap();
+
+        assert_that!(files.url("small"))
+            .is_ok_containing("http://localhost/documents/a/small_file");
+        assert_that!(files.url("large"))
+            .is_ok_containing("http://localhost/documents/a/large_file");
+        assert_that!(files.dimensions("small")).is_equal_to(Some((30, 20)));
+        assert_that!(files.dimensions("large")).is_none();
+    }
+
+    #[test]
+    fn creates_output_path_for_each_variant() -> anyhow::Result<()> {
+        let minutes = minutes_with_images(&["/input/section-1/1.jpg"]);
+        let layout = OutputLayout {
+            output_root: PathBuf::from("/output"),
+            variants: vec![
+                "thumb=320w".parse().unwrap(),
+                "medium=1024w".parse().unwrap(),
+            ],
+            naming: NamingScheme::default(),
+        };
+
+        let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+
+        let image_files = &for_conversion.sections[0].image_files[0];
+        assert_that!(image_files.variants).is_equal_to(BTreeMap::from([
+            (
+                "medium".to_string(),
+                PathBuf::from("/output/section-1/1_medium.jpg"),
+            ),
+            (
+                "thumb".to_string(),
+                PathBuf::from("/output/section-1/1_thumb.jpg"),
+            ),
+        ]));
+        Ok(())
+    }
+
+    #[test]
+    fn fails_when_two_images_map_to_the_same_name() {
+        let minutes =
+            minutes_with_images(&["/input/section-1/a b.jpg", "/input/section-1/A-B.jpg"]);
+        let layout = OutputLayout {
+            output_root: PathBuf::from("/output"),
+            variants: vec!["thumb=320w".parse().unwrap()],
+            naming: "{section}/{stem_slug}_{variant}.{ext}".parse().unwrap(),
+        };
+
+        let result = create_minutes_for_conversion(&minutes, &layout);
 
-        assert_that!(files.small_image)
-            .is_equal_to("http://localhost/documents/a/small_file".to_string());
-        assert_that!(files.large_image)
-            .is_equal_to("http://localhost/documents/a/large_file".to_string());
+        let err = assert_that!(result).is_err().subject.to_string();
+        assert_that!(err).contains("a-b_thumb.jpg would be created from several images");
     }
 }
@@ -0,0 +1,214 @@
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Errors of the library. Errors about a file carry its path, errors that occur while
+/// the images of a section are processed are wrapped in [`Error::InSection`].
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("{} does not exist", .path.display())]
+    SourceMissing { path: PathBuf },
+    #[error("could not read {}", .path.display())]
+    Read {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("could not write {}", .path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    #[error("{} exists already", .path.display())]
+    DestinationExists { path: PathBuf },
+    #[error("could not decode {}", .path.display())]
+    Decode {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+    #[error("could not encode {}", .path.display())]
+    Encode {
+        path: PathBuf,
+        #[source]
+        source: image::ImageError,
+    },
+    /// The image has no usable file name or does not fit its overrides.
+    #[error("{}: {message}", .path.display())]
+    InvalidImage { path: PathBuf, message: String },
+    #[error("could not read archive {}", .path.display())]
+    Archive {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+    /// A config, overrides or schedule file (or its content, if `path` is `None`)
+    /// is invalid.
+    #[error("could not parse {}: {message}", .path.as_deref().map(Path::display).map(|p| p.to_string()).unwrap_or_else(|| "content".to_string()))]
+    Parse {
+        path: Option<PathBuf>,
+        message: String,
+    },
+    #[error("{0}")]
+    InvalidConfig(String),
+    #[error("generated file name {0} is not a valid relative path")]
+    InvalidFileName(String),
+    #[error("{} would be created from several images: {}", .output_path.display(), .source_image_paths.iter().map(|p| p.display().to_string()).collect::<Vec<_>>().join(", "))]
+    NameCollision {
+        output_path: PathBuf,
+        source_image_paths: Vec<PathBuf>,
+    },
+    #[error("there is no image variant named {0}")]
+    UnknownVariant(String),
+    #[error("image {} is not below the output directory {}", .path.display(), .output_root.display())]
+    OutsideOutput { path: PathBuf, output_root: PathBuf },
+    #[error("could not listen on port {port}: {message}")]
+    Listen { port: u16, message: String },
+    #[error("could not publish to {target}: {message}")]
+    Publish { target: String, message: String },
+    #[error("{} URLs do not refer to published files: {}", .urls.len(), .urls.join(", "))]
+    Unpublished { urls: Vec<String> },
+    #[error("could not watch {} for changes", .path.display())]
+    Watch {
+        path: PathBuf,
+        #[source]
+        source: notify::Error,
+    },
+    #[error("in section {section}")]
+    InSection {
+        section: String,
+        #[source]
+        source: Box<Error>,
+    },
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    pub(crate) fn read(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| {
+            if source.kind() == io::ErrorKind::NotFound {
+                Error::SourceMissing { path }
+            } else {
+                Error::Read { path, source }
+            }
+        }
+    }
+
+    pub(crate) fn write(path: impl Into<PathBuf>) -> impl FnOnce(io::Error) -> Self {
+        let path = path.into();
+        move |source| {
+            if source.kind() == io::ErrorKind::AlreadyExists {
+                Error::DestinationExists { path }
+            } else {
+                Error::Write { path, source }
+            }
+        }
+    }
+
+    pub(crate) fn parse(message: impl ToString) -> Self {
+        Error::Parse {
+            path: None,
+            message: message.to_string(),
+        }
+    }
+
+    /// Adds the path of the parsed file to parse errors.
+    pub(crate) fn in_file(self, file: &Path) -> Self {
+        match self {
+            Error::Parse {
+                path: None,
+                message,
+            } => Error::Parse {
+                path: Some(file.to_path_buf()),
+                message,
+            },
+            other => other,
+        }
+    }
+
+    pub(crate) fn in_section(self, section: &str) -> Self {
+        Error::InSection {
+            section: section.to_string(),
+            source: Box::new(self),
+        }
+    }
+
+    /// The file the error is about, if any.
+    pub fn path(&self) -> Option<&Path> {
+        match self {
+            Error::SourceMissing { path }
+            | Error::Read { path, .. }
+            | Error::Write { path, .. }
+            | Error::DestinationExists { path }
+            | Error::Decode { path, .. }
+            | Error::Encode { path, .. }
+            | Error::InvalidImage { path, .. }
+            | Error::Archive { path, .. }
+            | Error::OutsideOutput { path, .. }
+            | Error::Watch { path, .. } => Some(path),
+            Error::Parse { path, .. } => path.as_deref(),
+            Error::NameCollision { output_path, .. } => Some(output_path),
+            Error::InSection { source, .. } => source.path(),
+            Error::InvalidConfig(_)
+            | Error::InvalidFileName(_)
+            | Error::UnknownVariant(_)
+            | Error::Listen { .. }
+            | Error::Publish { .. }
+            | Error::Unpublished { .. } => None,
+        }
+    }
+
+    /// The section that was processed when the error occurred, innermost first
+    /// for nested sections.
+    pub fn section(&self) -> Option<&str> {
+        match self {
+            Error::InSection { section, source } => source.section().or(Some(section)),
+            _ => None,
+        }
+    }
+
+    /// The error without section wrappers.
+    pub fn cause(&self) -> &Error {
+        match self {
+            Error::InSection { source, .. } => source.cause(),
+            other => other,
+        }
+    }
+
+    /// The message of the error and all of its sources, separated by colons.
+    pub fn full_message(&self) -> String {
+        let mut message = self.to_string();
+        let mut source = std::error::Error::source(self);
+        while let Some(error) = source {
+            message.push_str(": ");
+            message.push_str(&error.to_string());
+            source = error.source();
+        }
+        message
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+    use std::path::Path;
+
+    use speculoos::prelude::*;
+
+    use crate::error::Error;
+
+    #[test]
+    fn path_and_section_are_found_in_wrapped_errors() {
+        let error = Error::read("a/b.jpg")(io::Error::from(io::ErrorKind::NotFound))
+            .in_section("inner")
+            .in_section("outer");
+
+        assert_that!(error.path()).is_equal_to(Some(Path::new("a/b.jpg")));
+        assert_that!(error.section()).is_equal_to(Some("inner"));
+        assert_that!(matches!(error.cause(), Error::SourceMissing { .. })).is_true();
+        assert_that!(error.full_message())
+            .is_equal_to("in section outer: in section inner: a/b.jpg does not exist".to_string());
+    }
+}
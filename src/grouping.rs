@@ -0,0 +1,280 @@
+use std::path::Path;
+
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Deserializer};
+
+use crate::error::Error;
+use crate::images::SourceImagePath;
+
+pub const UNDATED_SECTION_NAME: &str = "Undated";
+pub const UNSCHEDULED_SECTION_NAME: &str = "Unscheduled";
+
+const SCHEDULE_DATE_TIME_FORMATS: [&str; 2] = ["%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S"];
+
+/// How photos of a flat directory are split into sections by their capture time.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Grouping {
+    /// A new section starts whenever two consecutive photos are further apart.
+    Gap(Duration),
+    Schedule(Schedule),
+}
+
+#[derive(Deserialize, Eq, PartialEq, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Schedule {
+    #[serde(rename = "slot")]
+    pub slots: Vec<Slot>,
+}
+
+#[derive(Deserialize, Eq, PartialEq, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Slot {
+    pub title: String,
+    #[serde(deserialize_with = "deserialize_date_time")]
+    pub start: NaiveDateTime,
+    #[serde(deserialize_with = "deserialize_date_time")]
+    pub end: NaiveDateTime,
+}
+
+fn deserialize_date_time<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<NaiveDateTime, D::Error> {
+    let value = String::deserialize(deserializer)?;
+    SCHEDULE_DATE_TIME_FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(&value, format).ok())
+        .ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "invalid date and time {}, expected e.g. 2024-05-17 09:30",
+                value
+            ))
+        })
+}
+
+impl Schedule {
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let content = fs_err::read_to_string(path).map_err(Error::read(path))?;
+        Self::parse(&content).map_err(|e| e.in_file(path))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        let schedule: Schedule = toml::from_str(content).map_err(Error::parse)?;
+        if let Some(slot) = schedule.slots.iter().find(|s| s.end <= s.start) {
+            return Err(Error::parse(format!(
+                "slot {} ends before it starts",
+                slot.title
+            )));
+        }
+        Ok(schedule)
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct DatedImage {
+    pub image: SourceImagePath,
+    pub capture_time: Option<NaiveDateTime>,
+}
+
+/// Splits the images into named groups in chronological order. Images without
+/// capture time (and, with a schedule, images outside of all slots) are put into
+/// trailing groups of their own.
+pub fn group_images(
+    mut images: Vec<DatedImage>,
+    grouping: &Grouping,
+) -> Vec<(String, Vec<SourceImagePath>)> {
+    images.sort_by(|a, b| (a.capture_time, a.image.path()).cmp(&(b.capture_time, b.image.path())));
+    let (dated, undated): (Vec<_>, Vec<_>) =
+        images.into_iter().partition(|i| i.capture_time.is_some());
+    let dated: Vec<_> = dated
+        .into_iter()
+        .filter_map(|i| Some((i.capture_time?, i.image)))
+        .collect();
+
+    let mut groups = match grouping {
+        Grouping::Gap(gap) => group_by_gap(dated, *gap),
+        Grouping::Schedule(schedule) => group_by_schedule(dated, schedule),
+    };
+    if !undated.is_empty() {
+        groups.push((
+            UNDATED_SECTION_NAME.to_string(),
+            undated.into_iter().map(|i| i.image).collect(),
+        ));
+    }
+    groups
+}
+
+fn group_by_gap(
+    images: Vec<(NaiveDateTime, SourceImagePath)>,
+    gap: Duration,
+) -> Vec<(String, Vec<SourceImagePath>)> {
+    let spans_several_days = images
+        .first()
+        .zip(images.last())
+        .is_some_and(|(first, last)| first.0.date() != last.0.date());
+    let mut groups: Vec<(NaiveDateTime, NaiveDateTime, Vec<SourceImagePath>)> = vec![];
+    for (capture_time, image) in images {
+        match groups.last_mut() {
+            Some((_, last, images)) if capture_time - *last <= gap => {
+                *last = capture_time;
+                images.push(image);
+            }
+            _ => groups.push((capture_time, capture_time, vec![image])),
+        }
+    }
+    let format = if spans_several_days {
+        "%Y-%m-%d %H:%M"
+    } else {
+        "%H:%M"
+    };
+    groups
+        .into_iter()
+        .map(|(first, last, images)| {
+            (
+                format!("{} – {}", first.format(format), last.format("%H:%M")),
+                images,
+            )
+        })
+        .collect()
+}
+
+fn group_by_schedule(
+    images: Vec<(NaiveDateTime, SourceImagePath)>,
+    schedule: &Schedule,
+) -> Vec<(String, Vec<SourceImagePath>)> {
+    let mut groups: Vec<_> = schedule
+        .slots
+        .iter()
+        .map(|s| (s.title.clone(), vec![]))
+        .collect();
+    let mut unscheduled = vec![];
+    for (capture_time, image) in images {
+        match schedule
+            .slots
+            .iter()
+            .position(|s| s.start <= capture_time && capture_time < s.end)
+        {
+            Some(index) => groups[index].1.push(image),
+            None => unscheduled.push(image),
+        }
+    }
+    groups.retain(|(_, images)| !images.is_empty());
+    if !unscheduled.is_empty() {
+        groups.push((UNSCHEDULED_SECTION_NAME.to_string(), unscheduled));
+    }
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use chrono::{Duration, NaiveDate, NaiveDateTime};
+    use speculoos::prelude::*;
+
+    use crate::grouping::{group_images, DatedImage, Grouping, Schedule};
+    use crate::images::SourceImagePath;
+
+    fn time(hour: u32, minute: u32) -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 5, 17)
+            .and_then(|d| d.and_hms_opt(hour, minute, 0))
+            .unwrap()
+    }
+
+    fn image(name: &str) -> SourceImagePath {
+        SourceImagePath::new(PathBuf::from("/input").join(name))
+    }
+
+    fn dated(name: &str, capture_time: Option<NaiveDateTime>) -> DatedImage {
+        DatedImage {
+            image: image(name),
+            capture_time,
+        }
+    }
+
+    #[test]
+    fn groups_by_gaps_between_photos() {
+        let images = vec![
+            dated("c.jpg", Some(time(11, 0))),
+            dated("a.jpg", Some(time(9, 0))),
+            dated("b.jpg", Some(time(9, 20))),
+            dated("d.jpg", None),
+        ];
+
+        let groups = group_images(images, &Grouping::Gap(Duration::minutes(30)));
+
+        assert_that!(groups).is_equal_to(vec![
+            (
+                "09:00 – 09:20".to_string(),
+                vec![image("a.jpg"), image("b.jpg")],
+            ),
+            ("11:00 – 11:00".to_string(), vec![image("c.jpg")]),
+            ("Undated".to_string(), vec![image("d.jpg")]),
+        ]);
+    }
+
+    #[test]
+    fn groups_by_schedule_slots() -> anyhow::Result<()> {
+        let schedule = Schedule::parse(
+            r#"
+            [[slot]]
+            title = "Keynote"
+            start = "2024-05-17 09:00"
+            end = "2024-05-17 10:00"
+
+            [[slot]]
+            title = "Lunch"
+            start = "2024-05-17 12:00"
+            end = "2024-05-17 13:00"
+
+            [[slot]]
+            title = "Open Space"
+            start = "2024-05-17 10:00"
+            end = "2024-05-17 12:00"
+            "#,
+        )?;
+        let images = vec![
+            dated("a.jpg", Some(time(9, 59))),
+            dated("b.jpg", Some(time(10, 0))),
+            dated("c.jpg", Some(time(18, 0))),
+        ];
+
+        let groups = group_images(images, &Grouping::Schedule(schedule));
+
+        assert_that!(groups).is_equal_to(vec![
+            ("Keynote".to_string(), vec![image("a.jpg")]),
+            ("Open Space".to_string(), vec![image("b.jpg")]),
+            ("Unscheduled".to_string(), vec![image("c.jpg")]),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_slots_that_end_before_they_start() {
+        let result = Schedule::parse(
+            r#"
+            [[slot]]
+            title = "Keynote"
+            start = "2024-05-17 10:00"
+            end = "2024-05-17 09:00"
+            "#,
+        );
+
+        let err = assert_that!(result).is_err().subject.to_string();
+        assert_that!(err).contains("Keynote ends before it starts");
+    }
+
+    #[test]
+    fn rejects_invalid_date_times() {
+        let result = Schedule::parse(
+            r#"
+            [[slot]]
+            title = "Keynote"
+            start = "9:00"
+            end = "2024-05-17 10:00"
+            "#,
+        );
+
+        let err = assert_that!(result).is_err().subject.to_string();
+        assert_that!(err).contains("invalid date and time 9:00");
+    }
+}
@@ -0,0 +1,230 @@
+use crate::error::Error;
+use crate::output::{MinutesForOutput, SectionForOutput};
+use crate::OutputImageFiles;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct HtmlOptions {
+    pub thumbnail_variant: String,
+    pub link_variant: String,
+    /// Variants offered in `srcset` together with their width descriptor.
+    pub srcset: Vec<(String, u32)>,
+    pub sizes: String,
+}
+
+impl Default for HtmlOptions {
+    fn default() -> Self {
+        Self {
+            thumbnail_variant: "small".to_string(),
+            link_variant: "large".to_string(),
+            srcset: vec![],
+            sizes: "100vw".to_string(),
+        }
+    }
+}
+
+pub fn create_html(minutes: MinutesForOutput, options: &HtmlOptions) -> Result<String, Error> {
+    let sections = minutes
+        .sections
+        .into_iter()
+        .map(|s| format_section(s, 1, options))
+        .collect::<Result<Vec<_>, Error>>()?
+        .join("\n\n");
+
+    Ok(sections)
+}
+
+/// HTML only knows six heading levels, deeper sections share the last one.
+const MAX_HEADING_LEVEL: usize = 6;
+
+fn format_section(
+    s: SectionForOutput,
+    level: usize,
+    options: &HtmlOptions,
+) -> Result<String, Error> {
+    let name = escape(s.name);
+    let level = level.min(MAX_HEADING_LEVEL);
+    let images = s
+        .image_files
+        .iter()
+        .map(|f| format_image(f, &name, options))
+        .collect::<Result<Vec<_>, Error>>()?;
+    let mut lines = if name.is_empty() {
+        vec![]
+    } else {
+        vec![format!("<h{}>{}</h{}>", level, name, level)]
+    };
+    lines.extend(images);
+    let mut parts = vec![lines.join("\n")];
+    for subsection in s.subsections {
+        parts.push(format_section(subsection, level + 1, options)?);
+    }
+    Ok(parts.join("\n\n"))
+}
+
+fn format_image(
+    files: &OutputImageFiles,
+    alt: &str,
+    options: &HtmlOptions,
+) -> Result<String, Error> {
+    let srcset = options
+        .srcset
+        .iter()
+        .map(|(variant, width)| Ok(format!("{} {}w", escape(files.url(variant)?), width)))
+        .collect::<Result<Vec<_>, Error>>()?
+        .join(", ");
+    let srcset_attributes = if srcset.is_empty() {
+        String::new()
+    } else {
+        format!(
+            " srcset=\"{}\" sizes=\"{}\"",
+            srcset,
+            escape(&options.sizes)
+        )
+    };
+    Ok(format!(
+        "<a href=\"{}\"><img src=\"{}\"{}{} alt=\"{}\"></a>",
+        escape(files.url(&options.link_variant)?),
+        escape(files.url(&options.thumbnail_variant)?),
+        srcset_attributes,
+        dimension_attributes(files.dimensions(&options.thumbnail_variant)),
+        alt
+    ))
+}
+
+/// `width` and `height` attributes, so that browsers can reserve the space
+/// before the image is loaded.
+pub(crate) fn dimension_attributes(dimensions: Option<(u32, u32)>) -> String {
+    dimensions
+        .map(|(width, height)| format!(" width=\"{}\" height=\"{}\"", width, height))
+        .unwrap_or_default()
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use speculoos::prelude::*;
+
+    use crate::html_output::{create_html, HtmlOptions};
+    use crate::output::{MinutesForOutput, SectionForOutput};
+    use crate::OutputImageFiles;
+
+    fn minutes_with_one_image(section_name: &str) -> MinutesForOutput<'_> {
+        MinutesForOutput {
+            sections: vec![SectionForOutput {
+                name: section_name,
+                image_files: vec![OutputImageFiles {
+                    variants: BTreeMap::from([
+                        ("thumb".to_string(), "/a/1_thumb.jpg".to_string()),
+                        ("medium".to_string(), "/a/1_medium.jpg".to_string()),
+                        ("large".to_string(), "/a/1_large.jpg".to_string()),
+                    ]),
+                    dimensions: BTreeMap::new(),
+                }],
+                subsections: vec![],
+            }],
+        }
+    }
+
+    #[test]
+    fn creates_heading_and_linked_image() {
+        let options = HtmlOptions {
+            thumbnail_variant: "thumb".to_string(),
+            ..HtmlOptions::default()
+        };
+
+        let html = assert_that!(create_html(minutes_with_one_image("section 1"), &options))
+            .is_ok()
+            .subject
+            .to_string();
+
+        assert_that!(html).is_equal_to(
+            "<h1>section 1</h1>\n<a href=\"/a/1_large.jpg\"><img src=\"/a/1_thumb.jpg\" alt=\"section 1\"></a>"
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn creates_srcset_for_selected_variants() {
+        let options = HtmlOptions {
+            thumbnail_variant: "thumb".to_string(),
+            link_variant: "large".to_string(),
+            srcset: vec![("thumb".to_string(), 320), ("medium".to_string(), 1024)],
+            sizes: "(max-width: 600px) 100vw, 50vw".to_string(),
+        };
+
+        let html = assert_that!(create_html(minutes_with_one_image("section 1"), &options))
+            .is_ok()
+            .subject
+            .to_string();
+
+        assert_that!(html).contains(
+            "srcset=\"/a/1_thumb.jpg 320w, /a/1_medium.jpg 1024w\" sizes=\"(max-width: 600px) 100vw, 50vw\"",
+        );
+    }
+
+    #[test]
+    fn escapes_section_names() {
+        let options = HtmlOptions {
+            thumbnail_variant: "thumb".to_string(),
+            ..HtmlOptions::default()
+        };
+
+        let html = assert_that!(create_html(minutes_with_one_image("Q&A <live>"), &options))
+            .is_ok()
+            .subject
+            .to_string();
+
+        assert_that!(html).contains("<h1>Q&amp;A &lt;live&gt;</h1>");
+        assert_that!(html).contains("alt=\"Q&amp;A &lt;live&gt;\"");
+    }
+
+    #[test]
+    fn heading_level_follows_section_depth() {
+        let minutes = MinutesForOutput {
+            sections: vec![SectionForOutput {
+                name: "track",
+                image_files: vec![],
+                subsections: vec![minutes_with_one_image("session").sections.remove(0)],
+            }],
+        };
+        let options = HtmlOptions {
+            thumbnail_variant: "thumb".to_string(),
+            ..HtmlOptions::default()
+        };
+
+        let html = assert_that!(create_html(minutes, &options))
+            .is_ok()
+            .subject
+            .to_string();
+
+        assert_that!(html).starts_with("<h1>track</h1>\n\n<h2>session</h2>\n");
+    }
+
+    #[test]
+    fn adds_dimensions_of_the_thumbnail() {
+        let mut minutes = minutes_with_one_image("section 1");
+        minutes.sections[0].image_files[0].dimensions =
+            BTreeMap::from([("thumb".to_string(), (300, 200))]);
+        let options = HtmlOptions {
+            thumbnail_variant: "thumb".to_string(),
+            ..HtmlOptions::default()
+        };
+
+        let html = assert_that!(create_html(minutes, &options))
+            .is_ok()
+            .subject
+            .to_string();
+
+        assert_that!(html).contains(
+            "<img src=\"/a/1_thumb.jpg\" width=\"300\" height=\"200\" alt=\"section 1\">",
+        );
+    }
+}
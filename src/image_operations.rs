@@ -1,54 +1,629 @@
-use std::io::Write;
-use std::path::{Path, PathBuf};
+use std::fmt::{Display, Formatter};
+use std::fs::File;
+use std::io::{self, BufRead, BufWriter, Seek, SeekFrom};
+use std::ops::AddAssign;
+use std::path::Path;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
 
-use anyhow::Context;
 use exif::{In, Tag};
 use image::imageops::FilterType;
-use image::{imageops, DynamicImage};
+use image::{imageops, DynamicImage, GrayImage, RgbImage};
+use serde::{Deserialize, Serialize};
 
-pub fn save_as_resized_image<S: AsRef<Path>, D: AsRef<Path>>(
-    source_image_path: S,
+use crate::atomic_file;
+use crate::error::Error;
+use crate::images::SourceImagePath;
+use crate::overrides::{Crop, ImageOverrides};
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum ResamplingFilter {
+    Nearest,
+    #[default]
+    Triangle,
+    CatmullRom,
+    Gaussian,
+    Lanczos3,
+}
+
+impl FromStr for ResamplingFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "nearest" => Ok(ResamplingFilter::Nearest),
+            "triangle" => Ok(ResamplingFilter::Triangle),
+            "catmullrom" | "catmull-rom" => Ok(ResamplingFilter::CatmullRom),
+            "gaussian" => Ok(ResamplingFilter::Gaussian),
+            "lanczos3" => Ok(ResamplingFilter::Lanczos3),
+            other => Err(format!(
+                "unknown resampling filter {}, expected one of nearest, triangle, catmull-rom, gaussian, lanczos3",
+                other
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for ResamplingFilter {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for ResamplingFilter {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ResamplingFilter::Nearest => "nearest",
+            ResamplingFilter::Triangle => "triangle",
+            ResamplingFilter::CatmullRom => "catmull-rom",
+            ResamplingFilter::Gaussian => "gaussian",
+            ResamplingFilter::Lanczos3 => "lanczos3",
+        })
+    }
+}
+
+impl From<ResamplingFilter> for String {
+    fn from(value: ResamplingFilter) -> Self {
+        value.to_string()
+    }
+}
+
+impl From<ResamplingFilter> for FilterType {
+    fn from(value: ResamplingFilter) -> Self {
+        match value {
+            ResamplingFilter::Nearest => FilterType::Nearest,
+            ResamplingFilter::Triangle => FilterType::Triangle,
+            ResamplingFilter::CatmullRom => FilterType::CatmullRom,
+            ResamplingFilter::Gaussian => FilterType::Gaussian,
+            ResamplingFilter::Lanczos3 => FilterType::Lanczos3,
+        }
+    }
+}
+
+/// How the dimensions of a generated image are derived from the correctly oriented
+/// (and cropped) source. Images are never enlarged.
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub enum Sizing {
+    /// Fraction of the source area.
+    Ratio(f32),
+    MaxWidth(u32),
+    BoundingBox {
+        width: u32,
+        height: u32,
+    },
+}
+
+impl Sizing {
+    pub fn target_dimensions(&self, width: u32, height: u32) -> (u32, u32) {
+        let scale = match *self {
+            Sizing::Ratio(ratio) => ratio.sqrt(),
+            Sizing::MaxWidth(max_width) => max_width as f32 / width as f32,
+            Sizing::BoundingBox {
+                width: max_width,
+                height: max_height,
+            } => (max_width as f32 / width as f32).min(max_height as f32 / height as f32),
+        };
+        if scale >= 1.0 {
+            return (width, height);
+        }
+        (
+            scale_dimension(scale, width),
+            scale_dimension(scale, height),
+        )
+    }
+
+    fn keeps_original(&self) -> bool {
+        *self == Sizing::Ratio(1.0)
+    }
+}
+
+impl FromStr for Sizing {
+    type Err = String;
+
+    /// Parses `original`, a ratio like `0.3`, a maximum width like `320w`
+    /// or a bounding box like `800x600`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid size {}, expected `original`, a ratio like `0.3`, a width like `320w` or a box like `800x600`",
+                s
+            )
+        };
+        if s == "original" {
+            return Ok(Sizing::Ratio(1.0));
+        }
+        if let Some(width) = s.strip_suffix('w') {
+            return width
+                .parse()
+                .ok()
+                .filter(|w| *w > 0)
+                .map(Sizing::MaxWidth)
+                .ok_or_else(invalid);
+        }
+        if let Some((width, height)) = s.split_once('x') {
+            return match (width.parse(), height.parse()) {
+                (Ok(width), Ok(height)) if width > 0 && height > 0 => {
+                    Ok(Sizing::BoundingBox { width, height })
+                }
+                _ => Err(invalid()),
+            };
+        }
+        s.parse()
+            .ok()
+            .filter(|r| *r > 0.0 && *r <= 1.0)
+            .map(Sizing::Ratio)
+            .ok_or_else(invalid)
+    }
+}
+
+impl Display for Sizing {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            _ if self.keeps_original() => f.write_str("original"),
+            Sizing::Ratio(ratio) => write!(f, "{}", ratio),
+            Sizing::MaxWidth(width) => write!(f, "{}w", width),
+            Sizing::BoundingBox { width, height } => write!(f, "{}x{}", width, height),
+        }
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Copy)]
+pub struct ResizeOptions {
+    pub sizing: Sizing,
+    pub filter: ResamplingFilter,
+}
+
+/// A named version of every image, e.g. `thumb` for thumbnails.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct Variant {
+    pub name: String,
+    pub resize: ResizeOptions,
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    /// Parses `NAME=SIZE` or `NAME=SIZE:FILTER`, e.g. `thumb=320w:lanczos3`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, spec) = s
+            .split_once('=')
+            .ok_or_else(|| format!("invalid variant {}, expected NAME=SIZE[:FILTER]", s))?;
+        if name.is_empty()
+            || !name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+        {
+            return Err(format!(
+                "invalid variant name {}, only ASCII letters, digits, - and _ are allowed",
+                name
+            ));
+        }
+        let (sizing, filter) = match spec.split_once(':') {
+            Some((sizing, filter)) => (sizing, filter.parse()?),
+            None => (spec, ResamplingFilter::default()),
+        };
+        Ok(Variant {
+            name: name.to_string(),
+            resize: ResizeOptions {
+                sizing: sizing.parse()?,
+                filter,
+            },
+        })
+    }
+}
+
+impl TryFrom<String> for Variant {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for Variant {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}={}:{}",
+            self.name, self.resize.sizing, self.resize.filter
+        )
+    }
+}
+
+impl From<Variant> for String {
+    fn from(value: Variant) -> Self {
+        value.to_string()
+    }
+}
+
+/// Sources larger than this multiple of the target size are first reduced cheaply
+/// (JPEG DCT scaling, then box sampling) to twice the target size before the
+/// selected filter is applied.
+const FAST_DOWNSCALE_THRESHOLD: u32 = 3;
+
+/// Time spent in the steps of [`save_as_resized_image`]. Copying an unchanged image
+/// counts as encoding.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct StepDurations {
+    pub decoding: Duration,
+    /// Includes rotating and cropping.
+    pub resizing: Duration,
+    pub encoding: Duration,
+}
+
+impl AddAssign for StepDurations {
+    fn add_assign(&mut self, other: Self) {
+        self.decoding += other.decoding;
+        self.resizing += other.resizing;
+        self.encoding += other.encoding;
+    }
+}
+
+/// What [`save_as_resized_image`] created.
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+pub struct SavedImage {
+    /// Dimensions as shown, i.e. with the EXIF orientation of copied images applied.
+    pub width: u32,
+    pub height: u32,
+    /// Sizes of the source and the created file in bytes.
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    pub durations: StepDurations,
+}
+
+/// With `overwrite`, an existing file at `dest_image_path` is replaced once the new one
+/// is complete, otherwise the existing file is an error.
+pub fn save_as_resized_image<D: AsRef<Path>>(
+    source_image: &SourceImagePath,
     dest_image_path: D,
-    ratio: f32,
-) -> anyhow::Result<()> {
-    if ratio == 1.0 {
-        fs_err::create_dir_all(
-            dest_image_path
-                .as_ref()
-                .parent()
-                .with_context(|| "image destination path has no parent")?,
-        )?;
-        fs_err::copy(source_image_path, dest_image_path)?;
-        return Ok(());
-    }
-
-    let source_image = image::ImageReader::open(&source_image_path)
-        .with_context(|| "source file does not exist")?
-        .decode()?;
-
-    let source_width = source_image.width();
-    let source_height = source_image.height();
-    let new_width = calculate_new_dimension(ratio, source_width);
-    let new_height = calculate_new_dimension(ratio, source_height);
-    let dest_image = source_image.resize(new_width, new_height, FilterType::Triangle);
-    let dest_image = rotate(
-        dest_image,
-        get_jpeg_orientation(PathBuf::from(source_image_path.as_ref()))?,
-    );
-    let dest_image_path = dest_image_path.as_ref();
-    dest_image_path.parent().map(fs_err::create_dir_all);
-    let mut dest_file = fs_err::OpenOptions::new()
-        .write(true)
-        .create_new(true)
-        .open(dest_image_path)
-        .context("destination file exists")?;
-    dest_image.write_to(&mut dest_file, image::ImageFormat::Jpeg)?;
-    dest_file.flush()?;
-    Ok(())
-}
-
-fn calculate_new_dimension(ratio: f32, source_width: u32) -> u32 {
-    (ratio.sqrt() * source_width as f32) as u32
+    resize: &ResizeOptions,
+    overrides: &ImageOverrides,
+    overwrite: bool,
+) -> Result<SavedImage, Error> {
+    let source = SourceImage::probe(source_image, overrides)?;
+    if resize.sizing.keeps_original() && !overrides.changes_pixels() {
+        let dest_image_path = dest_image_path.as_ref();
+        let start = Instant::now();
+        let mut reader = source_image.open()?;
+        let mut bytes_out = 0;
+        let copy = |dest_file: &mut BufWriter<File>| {
+            bytes_out = io::copy(&mut reader, dest_file).map_err(Error::write(dest_image_path))?;
+            Ok(())
+        };
+        if overwrite {
+            atomic_file::replace(dest_image_path, copy)?;
+        } else {
+            atomic_file::create_new(dest_image_path, copy)?;
+        }
+        let (width, height) = source.oriented_dimensions();
+        return Ok(SavedImage {
+            width,
+            height,
+            bytes_in: source.size,
+            bytes_out,
+            durations: StepDurations {
+                encoding: start.elapsed(),
+                ..StepDurations::default()
+            },
+        });
+    }
+
+    let (full_width, full_height) = source.oriented_dimensions();
+    let region = region_dimensions((full_width, full_height), overrides.crop);
+    let (target_width, target_height) = resize.sizing.target_dimensions(region.0, region.1);
+    let min_scale = (2.0 * target_width as f32 / region.0 as f32)
+        .max(2.0 * target_height as f32 / region.1 as f32);
+
+    let bytes_in = source.size;
+    let start = Instant::now();
+    let decoded = source.decode(min_scale)?;
+    let decoding = start.elapsed();
+    let start = Instant::now();
+    let transformed = decoded.orient().transform(overrides.crop, resize)?;
+    let resizing = start.elapsed();
+    let start = Instant::now();
+    let bytes_out = transformed.encode(dest_image_path, overwrite)?;
+    Ok(SavedImage {
+        width: transformed.width(),
+        height: transformed.height(),
+        bytes_in,
+        bytes_out,
+        durations: StepDurations {
+            decoding,
+            resizing,
+            encoding: start.elapsed(),
+        },
+    })
+}
+
+/// Dimensions of the image [`save_as_resized_image`] creates, read from the header of
+/// the source image only.
+pub fn resized_dimensions(
+    source_image: &SourceImagePath,
+    resize: &ResizeOptions,
+    overrides: &ImageOverrides,
+) -> Result<(u32, u32), Error> {
+    let source = SourceImage::probe(source_image, overrides)?;
+    let region = region_dimensions(source.oriented_dimensions(), overrides.crop);
+    let target = resize.sizing.target_dimensions(region.0, region.1);
+    Ok(fit_within(region, target))
+}
+
+/// Dimensions of a generated image as shown, i.e. with its EXIF orientation applied.
+pub fn generated_dimensions(path: &Path) -> Result<(u32, u32), Error> {
+    let image = SourceImagePath::new(path.to_path_buf());
+    Ok(SourceImage::probe(&image, &ImageOverrides::default())?.oriented_dimensions())
+}
+
+/// Like resizing keeps the aspect ratio by fitting the image into the target dimensions.
+fn fit_within((width, height): (u32, u32), (max_width, max_height): (u32, u32)) -> (u32, u32) {
+    if (width, height) == (max_width, max_height) {
+        return (width, height);
+    }
+    let ratio = (max_width as f64 / width as f64).min(max_height as f64 / height as f64);
+    (
+        ((width as f64 * ratio).round() as u32).max(1),
+        ((height as f64 * ratio).round() as u32).max(1),
+    )
+}
+
+/// A source image of which only the header and the orientation have been read.
+#[derive(Debug, Clone)]
+pub struct SourceImage {
+    source: SourceImagePath,
+    width: u32,
+    height: u32,
+    orientation: u8,
+    /// Of the file in bytes.
+    size: u64,
+}
+
+impl SourceImage {
+    pub fn probe(source: &SourceImagePath, overrides: &ImageOverrides) -> Result<Self, Error> {
+        let mut reader = source.open()?;
+        let orientation = match overrides.rotate {
+            Some(rotation) => rotation.as_exif_orientation(),
+            None => get_jpeg_orientation(&mut reader),
+        };
+        let size = reader
+            .seek(SeekFrom::End(0))
+            .map_err(Error::read(source.path()))?;
+        reader.rewind().map_err(Error::read(source.path()))?;
+        let (width, height) = image::ImageReader::new(reader)
+            .with_guessed_format()
+            .map_err(Error::read(source.path()))?
+            .into_dimensions()
+            .map_err(|e| Error::Decode {
+                path: source.path().to_path_buf(),
+                source: e,
+            })?;
+        Ok(Self {
+            source: source.clone(),
+            width,
+            height,
+            orientation,
+            size,
+        })
+    }
+
+    /// Dimensions of the full-size image as it is meant to be looked at.
+    pub fn oriented_dimensions(&self) -> (u32, u32) {
+        if swaps_axes(self.orientation) {
+            (self.height, self.width)
+        } else {
+            (self.width, self.height)
+        }
+    }
+
+    /// Decodes the pixels, possibly using DCT scaling as long as the decoded image
+    /// is at least `min_scale` times the full size.
+    pub fn decode(self, min_scale: f32) -> Result<DecodedImage, Error> {
+        let image = match self.decode_jpeg_scaled(min_scale) {
+            Some(image) => image,
+            None => image::ImageReader::new(self.source.open()?)
+                .with_guessed_format()
+                .map_err(Error::read(self.source.path()))?
+                .decode()
+                .map_err(|e| Error::Decode {
+                    path: self.source.path().to_path_buf(),
+                    source: e,
+                })?,
+        };
+        Ok(DecodedImage {
+            scale: image.width() as f32 / self.width as f32,
+            image,
+            source: self,
+        })
+    }
+
+    /// Decodes the whole image to find corrupt or truncated files, which the decoding
+    /// for the conversion may accept with gray areas. JPEG images are decoded strictly
+    /// and at the smallest DCT scale, which is faster.
+    pub fn check_decodable(&self) -> Result<(), Error> {
+        let decode_error = |source| Error::Decode {
+            path: self.source.path().to_path_buf(),
+            source,
+        };
+        let reader = image::ImageReader::new(self.source.open()?)
+            .with_guessed_format()
+            .map_err(Error::read(self.source.path()))?;
+        if reader.format() != Some(image::ImageFormat::Jpeg) {
+            reader.decode().map_err(decode_error)?;
+            return Ok(());
+        }
+        let mut decoder = jpeg_decoder::Decoder::new(self.source.open()?);
+        decoder
+            .scale(1, 1)
+            .and_then(|_| decoder.decode())
+            .map_err(|e| {
+                decode_error(image::ImageError::Decoding(
+                    image::error::DecodingError::new(image::ImageFormat::Jpeg.into(), e),
+                ))
+            })?;
+        Ok(())
+    }
+
+    fn decode_jpeg_scaled(&self, min_scale: f32) -> Option<DynamicImage> {
+        if min_scale >= 0.5 {
+            return None;
+        }
+        let mut decoder = jpeg_decoder::Decoder::new(self.source.open().ok()?);
+        let (width, height) = decoder
+            .scale(
+                scale_dimension(min_scale, self.width).max(1) as u16,
+                scale_dimension(min_scale, self.height).max(1) as u16,
+            )
+            .ok()?;
+        let pixels = decoder.decode().ok()?;
+        let (width, height) = (u32::from(width), u32::from(height));
+        match decoder.info()?.pixel_format {
+            jpeg_decoder::PixelFormat::L8 => {
+                GrayImage::from_raw(width, height, pixels).map(DynamicImage::ImageLuma8)
+            }
+            jpeg_decoder::PixelFormat::RGB24 => {
+                RgbImage::from_raw(width, height, pixels).map(DynamicImage::ImageRgb8)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Decoded pixels in the orientation in which they are stored in the file.
+pub struct DecodedImage {
+    source: SourceImage,
+    image: DynamicImage,
+    scale: f32,
+}
+
+impl DecodedImage {
+    pub fn orient(self) -> OrientedImage {
+        let (full_width, full_height) = self.source.oriented_dimensions();
+        OrientedImage {
+            image: rotate(self.image, self.source.orientation),
+            source: self.source.source,
+            scale: self.scale,
+            full_width,
+            full_height,
+        }
+    }
+}
+
+/// Decoded pixels in the orientation in which the image is meant to be looked at.
+pub struct OrientedImage {
+    image: DynamicImage,
+    source: SourceImagePath,
+    scale: f32,
+    full_width: u32,
+    full_height: u32,
+}
+
+impl OrientedImage {
+    /// Applies the crop (given in full-size coordinates) and resizes the result.
+    pub fn transform(
+        self,
+        crop: Option<Crop>,
+        resize: &ResizeOptions,
+    ) -> Result<TransformedImage, Error> {
+        let region = region_dimensions((self.full_width, self.full_height), crop);
+        let image = match crop {
+            Some(crop) => self.crop(crop)?,
+            None => self.image,
+        };
+        let (target_width, target_height) = resize.sizing.target_dimensions(region.0, region.1);
+        Ok(TransformedImage(downscale(
+            image,
+            target_width,
+            target_height,
+            resize.filter,
+        )))
+    }
+
+    fn crop(&self, crop: Crop) -> Result<DynamicImage, Error> {
+        if !crop.fits_within(self.full_width, self.full_height) {
+            return Err(self.source.invalid(&format!(
+                "crop [{}, {}, {}, {}] is outside of the image",
+                crop.x, crop.y, crop.width, crop.height
+            )));
+        }
+        let x = scale_dimension(self.scale, crop.x);
+        let y = scale_dimension(self.scale, crop.y);
+        let width = scale_dimension(self.scale, crop.width).min(self.image.width() - x);
+        let height = scale_dimension(self.scale, crop.height).min(self.image.height() - y);
+        Ok(self.image.crop_imm(x, y, width.max(1), height.max(1)))
+    }
+}
+
+pub struct TransformedImage(DynamicImage);
+
+impl TransformedImage {
+    pub fn width(&self) -> u32 {
+        self.0.width()
+    }
+
+    pub fn height(&self) -> u32 {
+        self.0.height()
+    }
+
+    /// Nothing is changed at `dest_image_path` if encoding fails. With `overwrite`, an
+    /// existing file is replaced, otherwise it is an error. Returns the size of the
+    /// file in bytes.
+    pub fn encode(&self, dest_image_path: impl AsRef<Path>, overwrite: bool) -> Result<u64, Error> {
+        let dest_image_path = dest_image_path.as_ref();
+        let mut size = 0;
+        let write = |dest_file: &mut BufWriter<File>| {
+            self.0
+                .write_to(&mut *dest_file, image::ImageFormat::Jpeg)
+                .map_err(|e| Error::Encode {
+                    path: dest_image_path.to_path_buf(),
+                    source: e,
+                })?;
+            size = dest_file
+                .stream_position()
+                .map_err(Error::write(dest_image_path))?;
+            Ok(())
+        };
+        if overwrite {
+            atomic_file::replace(dest_image_path, write)?;
+        } else {
+            atomic_file::create_new(dest_image_path, write)?;
+        }
+        Ok(size)
+    }
+}
+
+fn scale_dimension(scale: f32, dimension: u32) -> u32 {
+    (scale * dimension as f32) as u32
+}
+
+fn region_dimensions(full: (u32, u32), crop: Option<Crop>) -> (u32, u32) {
+    crop.map(|c| (c.width, c.height)).unwrap_or(full)
+}
+
+fn swaps_axes(orientation: u8) -> bool {
+    (5..=8).contains(&orientation)
+}
+
+fn downscale(
+    image: DynamicImage,
+    new_width: u32,
+    new_height: u32,
+    filter: ResamplingFilter,
+) -> DynamicImage {
+    let image = if image.width() > FAST_DOWNSCALE_THRESHOLD * new_width
+        && image.height() > FAST_DOWNSCALE_THRESHOLD * new_height
+    {
+        image.thumbnail(2 * new_width, 2 * new_height)
+    } else {
+        image
+    };
+    if (image.width(), image.height()) == (new_width, new_height) {
+        return image;
+    }
+    image.resize(new_width, new_height, filter.into())
 }
 
 fn rotate(mut img: DynamicImage, orientation: u8) -> DynamicImage {
@@ -68,11 +643,9 @@ fn rotate(mut img: DynamicImage, orientation: u8) -> DynamicImage {
     }
     img
 }
-fn get_jpeg_orientation(file_path: PathBuf) -> anyhow::Result<u8> {
-    let file = std::fs::File::open(file_path).context("problem opening the file")?;
-    let mut bufreader = std::io::BufReader::new(&file);
+fn get_jpeg_orientation(reader: &mut (impl BufRead + Seek)) -> u8 {
     let exifreader = exif::Reader::new();
-    let exif = exifreader.read_from_container(&mut bufreader);
+    let exif = exifreader.read_from_container(reader);
 
     match exif {
         Ok(exif) => {
@@ -84,20 +657,29 @@ fn get_jpeg_orientation(file_path: PathBuf) -> anyhow::Result<u8> {
                 None => 1,
             };
 
-            Ok(orientation)
+            orientation
         }
-        Err(_) => Ok(0),
+        Err(_) => 0,
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
 
     use fs_err;
     use speculoos::prelude::*;
 
-    use crate::image_operations::save_as_resized_image;
+    use crate::archive::Archive;
+    use crate::error::Error;
+    use crate::image_operations::{
+        resized_dimensions, save_as_resized_image, ResamplingFilter, ResizeOptions, Sizing,
+        SourceImage, Variant,
+    };
+    use crate::images::SourceImagePath;
+    use crate::overrides::{Crop, ImageOverrides, Rotation};
 
     #[test]
     fn save_as_resized_image_works() -> anyhow::Result<()> {
@@ -110,9 +692,20 @@ mod tests {
         fs_err::create_dir(&dest_path)?;
         let dest_image_path = dest_path.join("abc.dest.jpg");
 
-        save_as_resized_image(source_image_path, dest_image_path.as_path(), 0.5)?;
+        let saved = save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(0.5),
+                filter: ResamplingFilter::Triangle,
+            },
+            &ImageOverrides::default(),
+            false,
+        )?;
 
         assert_that!(dest_image_path).exists();
+        assert_that!(saved.bytes_in).is_equal_to(fs_err::metadata(source_image_path)?.len());
+        assert_that!(saved.bytes_out).is_equal_to(fs_err::metadata(&dest_image_path)?.len());
         let dynamic_image = image::ImageReader::open(dest_image_path)?.decode()?;
         assert_that!(dynamic_image.width()).is_less_than_or_equal_to(72);
         assert_that!(dynamic_image.width()).is_greater_than_or_equal_to(68);
@@ -122,6 +715,34 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn images_are_decoded_from_archives() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let archive_path = dir.path().join("photos.zip");
+        let mut zip = zip::ZipWriter::new(fs_err::File::create(&archive_path)?);
+        zip.start_file("1.jpg", zip::write::SimpleFileOptions::default())?;
+        zip.write_all(&fs_err::read("./src/empty-100x200.jpg")?)?;
+        zip.finish()?;
+        let archive = Arc::new(Archive::open(&archive_path)?);
+        let dest_image_path = dir.path().join("dest").join("1.jpg");
+
+        save_as_resized_image(
+            &SourceImagePath::in_source(archive, PathBuf::from("1.jpg")),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::MaxWidth(50),
+                filter: ResamplingFilter::Triangle,
+            },
+            &ImageOverrides::default(),
+            false,
+        )?;
+
+        let dynamic_image = image::ImageReader::open(dest_image_path)?.decode()?;
+        assert_that!(dynamic_image.width()).is_equal_to(50);
+        assert_that!(dynamic_image.height()).is_equal_to(100);
+        Ok(())
+    }
+
     #[test]
     fn copies_input_image_when_ratio_is_1() -> anyhow::Result<()> {
         let dir = tempfile::tempdir()?;
@@ -133,9 +754,21 @@ mod tests {
         fs_err::create_dir(&dest_path)?;
         let dest_image_path = dest_path.join("abc.dest.jpg");
 
-        save_as_resized_image(source_image_path, dest_image_path.as_path(), 1.0)?;
+        let saved = save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(1.0),
+                filter: ResamplingFilter::Triangle,
+            },
+            &ImageOverrides::default(),
+            false,
+        )?;
 
         assert_that!(dest_image_path).exists();
+        let size = fs_err::metadata(source_image_path)?.len();
+        assert_that!(saved.bytes_in).is_equal_to(size);
+        assert_that!(saved.bytes_out).is_equal_to(size);
         let input_image = image::ImageReader::open(source_image_path)?.decode()?;
         let output_image = image::ImageReader::open(dest_image_path)?.decode()?;
 
@@ -156,7 +789,16 @@ mod tests {
         fs_err::create_dir(&dest_suffix_path)?;
         let dest_image_path = dest_path.join("abc.dest.jpg");
 
-        save_as_resized_image(source_image_path, dest_image_path.as_path(), 1.0)?;
+        save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(1.0),
+                filter: ResamplingFilter::Triangle,
+            },
+            &ImageOverrides::default(),
+            false,
+        )?;
 
         assert_that!(dest_image_path).exists();
         let input_image = image::ImageReader::open(source_image_path)?.decode()?;
@@ -181,7 +823,16 @@ mod tests {
             .join("sub-sub-dir")
             .join("abc.dest.jpg");
 
-        save_as_resized_image(source_image_path, dest_image_path.as_path(), 0.5)?;
+        save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(0.5),
+                filter: ResamplingFilter::Triangle,
+            },
+            &ImageOverrides::default(),
+            false,
+        )?;
 
         assert_that!(dest_image_path).exists();
         let dynamic_image = image::ImageReader::open(dest_image_path)?.decode()?;
@@ -204,9 +855,19 @@ mod tests {
         fs_err::create_dir(&dest_path)?;
         let dest_image_path = dest_path.join("abc.dest.jpg");
 
-        let res = save_as_resized_image(source_image_path, dest_image_path.as_path(), 0.5);
-        let err_desc = assert_that!(res).is_err().subject.to_string();
-        assert_that!(err_desc).contains("source file");
+        let res = save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(0.5),
+                filter: ResamplingFilter::Triangle,
+            },
+            &ImageOverrides::default(),
+            false,
+        );
+        let err = assert_that!(res).is_err().subject;
+        assert_that!(matches!(err, Error::SourceMissing { path } if path == source_image_path))
+            .is_true();
 
         Ok(())
     }
@@ -223,10 +884,244 @@ mod tests {
         let dest_image_path = dest_path.join("abc.dest.jpg");
         fs_err::File::create(&dest_image_path)?;
 
-        let res = save_as_resized_image(source_image_path, dest_image_path.as_path(), 0.5);
+        let res = save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(0.5),
+                filter: ResamplingFilter::Triangle,
+            },
+            &ImageOverrides::default(),
+            false,
+        );
+        let err = assert_that!(res).is_err().subject;
+        assert_that!(matches!(err, Error::DestinationExists { path } if path == &dest_image_path))
+            .is_true();
+
+        Ok(())
+    }
+
+    #[test]
+    fn rotation_override_replaces_exif_orientation() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source_image_path = Path::new("./src/empty-100x200.jpg");
+        let dest_image_path = dir.path().join("abc.dest.jpg");
+        let overrides = ImageOverrides {
+            rotate: Some(Rotation::Clockwise90),
+            ..ImageOverrides::default()
+        };
+
+        save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(1.0),
+                filter: ResamplingFilter::Triangle,
+            },
+            &overrides,
+            false,
+        )?;
+
+        let dynamic_image = image::ImageReader::open(dest_image_path)?.decode()?;
+        assert_that!(dynamic_image.width()).is_equal_to(200);
+        assert_that!(dynamic_image.height()).is_equal_to(100);
+
+        Ok(())
+    }
+
+    #[test]
+    fn crop_override_is_applied_before_resizing() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source_image_path = Path::new("./src/empty-100x200.jpg");
+        let dest_image_path = dir.path().join("abc.dest.jpg");
+        let overrides = ImageOverrides {
+            crop: Some(Crop {
+                x: 0,
+                y: 100,
+                width: 100,
+                height: 100,
+            }),
+            ..ImageOverrides::default()
+        };
+
+        save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(0.25),
+                filter: ResamplingFilter::Triangle,
+            },
+            &overrides,
+            false,
+        )?;
+
+        let dynamic_image = image::ImageReader::open(dest_image_path)?.decode()?;
+        assert_that!(dynamic_image.width()).is_equal_to(50);
+        assert_that!(dynamic_image.height()).is_equal_to(50);
+        let planned = resized_dimensions(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(0.25),
+                filter: ResamplingFilter::Triangle,
+            },
+            &overrides,
+        )?;
+        assert_that!(planned).is_equal_to((50, 50));
+
+        Ok(())
+    }
+
+    #[test]
+    fn fails_when_crop_is_outside_of_the_image() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source_image_path = Path::new("./src/empty-100x200.jpg");
+        let dest_image_path = dir.path().join("abc.dest.jpg");
+        let overrides = ImageOverrides {
+            crop: Some(Crop {
+                x: 50,
+                y: 0,
+                width: 100,
+                height: 100,
+            }),
+            ..ImageOverrides::default()
+        };
+
+        let res = save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(1.0),
+                filter: ResamplingFilter::Triangle,
+            },
+            &overrides,
+            false,
+        );
         let err_desc = assert_that!(res).is_err().subject.to_string();
-        assert_that!(err_desc).contains("destination file");
+        assert_that!(err_desc).contains("outside of the image");
+
+        Ok(())
+    }
+
+    #[test]
+    fn resampling_filter_is_parsed_case_insensitively() {
+        assert_that!("Lanczos3".parse::<ResamplingFilter>())
+            .is_ok_containing(ResamplingFilter::Lanczos3);
+        assert_that!("catmull-rom".parse::<ResamplingFilter>())
+            .is_ok_containing(ResamplingFilter::CatmullRom);
+        assert_that!("bicubic".parse::<ResamplingFilter>()).is_err();
+    }
+
+    #[test]
+    fn large_downscale_uses_fast_path_and_keeps_target_size() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source_image_path = Path::new("./src/empty-100x200.jpg");
+        let dest_image_path = dir.path().join("abc.dest.jpg");
+
+        save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::Ratio(0.01),
+                filter: ResamplingFilter::Lanczos3,
+            },
+            &ImageOverrides::default(),
+            false,
+        )?;
+
+        let dynamic_image = image::ImageReader::open(dest_image_path)?.decode()?;
+        assert_that!(dynamic_image.width()).is_equal_to(10);
+        assert_that!(dynamic_image.height()).is_equal_to(20);
+        let source = SourceImage::probe(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            &ImageOverrides::default(),
+        )?;
+        let decoded = source.decode(0.2)?;
+        assert_that!(decoded.scale).is_less_than(1.0);
+        assert_that!(decoded.image.width()).is_less_than(100);
 
         Ok(())
     }
+
+    #[test]
+    fn sizing_never_enlarges_images() {
+        assert_that!(Sizing::MaxWidth(320).target_dimensions(100, 200)).is_equal_to((100, 200));
+        assert_that!(Sizing::BoundingBox {
+            width: 400,
+            height: 400
+        }
+        .target_dimensions(100, 200))
+        .is_equal_to((100, 200));
+    }
+
+    #[test]
+    fn bounding_box_sizing_fits_the_limiting_axis() {
+        let sizing = Sizing::BoundingBox {
+            width: 50,
+            height: 50,
+        };
+
+        assert_that!(sizing.target_dimensions(100, 200)).is_equal_to((25, 50));
+        assert_that!(sizing.target_dimensions(200, 100)).is_equal_to((50, 25));
+    }
+
+    #[test]
+    fn max_width_sizing_uses_the_oriented_dimensions() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source_image_path = Path::new("./src/empty-100x200.jpg");
+        let dest_image_path = dir.path().join("abc.dest.jpg");
+        let overrides = ImageOverrides {
+            rotate: Some(Rotation::Clockwise270),
+            ..ImageOverrides::default()
+        };
+
+        save_as_resized_image(
+            &SourceImagePath::new(source_image_path.to_path_buf()),
+            dest_image_path.as_path(),
+            &ResizeOptions {
+                sizing: Sizing::MaxWidth(100),
+                filter: ResamplingFilter::Triangle,
+            },
+            &overrides,
+            false,
+        )?;
+
+        let dynamic_image = image::ImageReader::open(dest_image_path)?.decode()?;
+        assert_that!(dynamic_image.width()).is_equal_to(100);
+        assert_that!(dynamic_image.height()).is_equal_to(50);
+
+        Ok(())
+    }
+
+    #[test]
+    fn sizing_is_parsed_from_its_short_form() {
+        assert_that!("original".parse::<Sizing>()).is_ok_containing(Sizing::Ratio(1.0));
+        assert_that!("0.3".parse::<Sizing>()).is_ok_containing(Sizing::Ratio(0.3));
+        assert_that!("320w".parse::<Sizing>()).is_ok_containing(Sizing::MaxWidth(320));
+        assert_that!("800x600".parse::<Sizing>()).is_ok_containing(Sizing::BoundingBox {
+            width: 800,
+            height: 600,
+        });
+        assert_that!("1.5".parse::<Sizing>()).is_err();
+        assert_that!("0w".parse::<Sizing>()).is_err();
+    }
+
+    #[test]
+    fn variant_is_parsed_with_optional_filter() {
+        assert_that!("thumb=320w".parse::<Variant>()).is_ok_containing(Variant {
+            name: "thumb".to_string(),
+            resize: ResizeOptions {
+                sizing: Sizing::MaxWidth(320),
+                filter: ResamplingFilter::Triangle,
+            },
+        });
+        assert_that!("large=2048w:lanczos3".parse::<Variant>()).is_ok_containing(Variant {
+            name: "large".to_string(),
+            resize: ResizeOptions {
+                sizing: Sizing::MaxWidth(2048),
+                filter: ResamplingFilter::Lanczos3,
+            },
+        });
+        assert_that!("../thumb=320w".parse::<Variant>()).is_err();
+        assert_that!("thumb".parse::<Variant>()).is_err();
+    }
 }
@@ -1,103 +1,163 @@
-use std::ffi::OsString;
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use anyhow::Context;
+use sha2::{Digest, Sha256};
 
-#[derive(Eq, PartialEq, Debug, Clone)]
-pub struct SourceImagePath(PathBuf);
+use crate::error::Error;
+use crate::source::{FileSystemSource, ImageReader, MinutesSource};
+
+const SUPPORTED_EXTENSIONS: [&str; 2] = ["jpg", "jpeg"];
+
+pub fn is_supported_image(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .is_some_and(|e| SUPPORTED_EXTENSIONS.contains(&e.as_str()))
+}
+
+/// A source image, identified by its path within a [`MinutesSource`]. [`Self::path`]
+/// is the root of the source joined with that path, e.g. `photos.zip/session/1.jpg`,
+/// and is what images are compared by.
+#[derive(Debug, Clone)]
+pub struct SourceImagePath {
+    path: PathBuf,
+    source: Arc<dyn MinutesSource>,
+    source_path: PathBuf,
+}
 
 impl SourceImagePath {
+    /// An image file on disk.
     pub fn new(path_buf: PathBuf) -> Self {
-        Self(path_buf)
+        Self::in_source(Arc::new(FileSystemSource::new(PathBuf::new())), path_buf)
+    }
+
+    pub fn in_source(source: Arc<dyn MinutesSource>, source_path: PathBuf) -> Self {
+        Self {
+            path: source.root().join(&source_path),
+            source,
+            source_path,
+        }
     }
 
     pub fn path(&self) -> &Path {
-        self.0.as_path()
+        self.path.as_path()
     }
 
-    pub fn small_image_path(&self, output_root: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
-        anyhow::Ok(
-            output_root
-                .as_ref()
-                .join(self.create_output_file_name("small")?),
-        )
+    pub fn open(&self) -> Result<Box<dyn ImageReader>, Error> {
+        self.source.open(&self.source_path)
     }
 
-    pub fn large_image_path(&self, output_root: impl AsRef<Path>) -> anyhow::Result<PathBuf> {
-        anyhow::Ok(
-            output_root
-                .as_ref()
-                .join(self.create_output_file_name("large")?),
-        )
+    pub fn file_stem(&self) -> Result<String, Error> {
+        Ok(self
+            .path
+            .file_stem()
+            .ok_or_else(|| self.invalid("has no file stem"))?
+            .to_string_lossy()
+            .to_string())
     }
 
-    fn create_output_file_name(&self, suffix: &str) -> anyhow::Result<PathBuf> {
-        let path = self.0.as_path();
-        let path_str = path.to_string_lossy();
+    pub fn extension(&self) -> Result<String, Error> {
+        Ok(self
+            .path
+            .extension()
+            .ok_or_else(|| self.invalid("has no extension"))?
+            .to_string_lossy()
+            .to_string())
+    }
 
-        let parent_file_name = path
-            .parent()
-            .with_context(|| format!("path <{}> has no parent", path_str))?
-            .file_name()
-            .with_context(|| format!("cannot find direct parent for {}", path_str))?;
+    /// Hex encoded SHA-256 of the file content.
+    pub fn content_hash(&self) -> Result<String, Error> {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut self.open()?, &mut hasher).map_err(Error::read(&self.path))?;
+        Ok(hasher
+            .finalize()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect())
+    }
 
-        let mut stem: OsString = path
-            .file_stem()
-            .with_context(|| format!("path has no file stem {}", path_str))?
-            .into();
-        stem.push("_");
-        stem.push(suffix);
-        let extension = path
-            .extension()
-            .with_context(|| format!("path <{}> has no extension", path_str))?;
+    pub(crate) fn invalid(&self, message: &str) -> Error {
+        Error::InvalidImage {
+            path: self.path.clone(),
+            message: message.to_string(),
+        }
+    }
+}
 
-        anyhow::Ok(
-            PathBuf::from(parent_file_name)
-                .join(stem)
-                .with_extension(extension),
-        )
+impl PartialEq for SourceImagePath {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
     }
 }
 
+impl Eq for SourceImagePath {}
+
 #[cfg(test)]
 mod tests {
+    use std::io::{Read, Write};
     use std::path::{Path, PathBuf};
+    use std::sync::Arc;
 
     use speculoos::prelude::*;
 
-    use crate::images::SourceImagePath;
+    use crate::archive::Archive;
+    use crate::images::{is_supported_image, SourceImagePath};
 
     #[test]
-    fn create_small_image_path() {
+    fn file_stem_and_extension_are_taken_from_the_path() {
         let image_path = SourceImagePath::new(PathBuf::from("/input/section-1/1.jpg"));
 
-        assert_that!(image_path.small_image_path(Path::new("/output")))
-            .is_ok_containing(PathBuf::from("/output/section-1/1_small.jpg"));
+        assert_that!(image_path.file_stem()).is_ok_containing("1".to_string());
+        assert_that!(image_path.extension()).is_ok_containing("jpg".to_string());
     }
 
     #[test]
-    fn create_small_image_path_fails_when_file_has_root_as_parent() {
-        let image_path = SourceImagePath::new(PathBuf::from("/1.jpg"));
+    fn extension_fails_when_file_has_none() {
+        let image_path = SourceImagePath::new(PathBuf::from("/input/section-1/1"));
 
-        let result = image_path.small_image_path(Path::new("/output"));
+        let result = image_path.extension();
         let err = assert_that!(result).is_err().subject;
-        assert_that!(err.to_string()).contains("direct parent for /1.jpg");
+        assert_that!(err.to_string()).contains("has no extension");
     }
 
     #[test]
-    fn create_large_image_path() {
-        let image_path = SourceImagePath::new(PathBuf::from("/input/section-1/1.jpg"));
-
-        assert_that!(image_path.large_image_path(Path::new("/output")))
-            .is_ok_containing(PathBuf::from("/output/section-1/1_large.jpg"));
+    fn jpeg_files_are_supported_images() {
+        assert_that!(is_supported_image(Path::new("/a/1.jpg"))).is_true();
+        assert_that!(is_supported_image(Path::new("/a/1.JPEG"))).is_true();
+        assert_that!(is_supported_image(Path::new("/a/notes.txt"))).is_false();
+        assert_that!(is_supported_image(Path::new("/a/jpg"))).is_false();
     }
 
     #[test]
-    fn create_large_image_path_fails_when_file_has_root_as_parent() {
-        let image_path = SourceImagePath::new(PathBuf::from("/1.jpg"));
+    fn content_hash_is_sha256_of_the_file() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("1.jpg");
+        fs_err::write(&path, "abc")?;
 
-        let result = image_path.large_image_path(Path::new("/output"));
-        let err = assert_that!(result).is_err().subject;
-        assert_that!(err.to_string()).contains("direct parent for /1.jpg");
+        let hash = SourceImagePath::new(path).content_hash()?;
+
+        assert_that!(hash).is_equal_to(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad".to_string(),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn archive_entries_are_read_from_the_archive() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let archive_path = dir.path().join("photos.zip");
+        let mut zip = zip::ZipWriter::new(fs_err::File::create(&archive_path)?);
+        zip.start_file("1.jpg", zip::write::SimpleFileOptions::default())?;
+        zip.write_all(b"abc")?;
+        zip.finish()?;
+
+        let archive = Arc::new(Archive::open(&archive_path)?);
+        let image = SourceImagePath::in_source(archive, PathBuf::from("1.jpg"));
+        let mut content = String::new();
+        image.open()?.read_to_string(&mut content)?;
+
+        assert_that!(image.path()).is_equal_to(archive_path.join("1.jpg").as_path());
+        assert_that!(content).is_equal_to("abc".to_string());
+        assert_that!(image.content_hash()?).starts_with("ba7816bf");
+        Ok(())
     }
 }
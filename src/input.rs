@@ -1,23 +1,144 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
 use fs_err;
 
-use crate::images::SourceImagePath;
+use crate::capture_time::read_capture_time;
+use crate::config::CONFIG_FILE_NAME;
+use crate::error::Error;
+use crate::grouping::{group_images, DatedImage, Grouping};
+use crate::images::{is_supported_image, SourceImagePath};
+use crate::overrides::{SectionOverrides, OVERRIDES_FILE_NAME};
+use crate::source::{open_source, FileSystemSource, MinutesSource};
 
 #[derive(Debug)]
 pub struct Minutes {
     pub sections: Vec<Section>,
+    /// Files in the input root that are neither section directories nor images.
+    pub ignored_files: Vec<PathBuf>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct InputOptions {
+    /// Name of the section that collects the images placed directly in the input root.
+    /// An empty name creates a section without heading.
+    pub introduction_title: String,
+}
+
+impl Default for InputOptions {
+    fn default() -> Self {
+        Self {
+            introduction_title: "General".to_string(),
+        }
+    }
+}
+
+impl Minutes {
+    /// Reads the sections from `root`, which is either a directory or a `.zip`, `.tar`,
+    /// `.tar.gz` or `.tgz` archive.
+    pub fn read(root: &Path, options: &InputOptions) -> Result<Self, Error> {
+        Self::read_from_source(open_source(root)?, options)
+    }
+
+    /// Reads the sections from the top-level directories of `source`.
+    pub fn read_from_source(
+        source: Arc<dyn MinutesSource>,
+        options: &InputOptions,
+    ) -> Result<Self, Error> {
+        let root = Path::new("");
+        let mut overrides = SectionOverrides::read_from_source(source.as_ref(), root)?;
+        let (dirs, files) = list(&source, root)?;
+        overrides.match_files(&files);
+        let mut sections = dirs
+            .iter()
+            .map(|dir| Section::read(&source, dir))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut root_images = vec![];
+        let mut ignored_files = vec![];
+        for file in files {
+            if !is_supported_image(file.path()) {
+                ignored_files.push(file.path().to_path_buf());
+            } else if !overrides.for_image(&file).skip {
+                root_images.push(file);
+            }
+        }
+        sections.sort_by(|a, b| a.name.cmp(&b.name));
+        ignored_files.sort();
+        if !root_images.is_empty() {
+            root_images.sort_by(|a, b| a.path().cmp(b.path()));
+            sections.insert(
+                0,
+                Section {
+                    name: options.introduction_title.clone(),
+                    image_files: root_images,
+                    overrides,
+                    subsections: vec![],
+                },
+            );
+        }
+        Ok(Self {
+            sections,
+            ignored_files,
+        })
+    }
+
+    /// Reads a single directory (or archive) of photos and splits them into sections by
+    /// the time they were taken. The overrides of the directory apply to all sections.
+    pub fn read_flat(dir: &Path, grouping: &Grouping) -> Result<Self, Error> {
+        Self::read_flat_from_source(open_source(dir)?, grouping)
+    }
+
+    /// Like [`Self::read_flat`] for the top-level directory of `source`.
+    pub fn read_flat_from_source(
+        source: Arc<dyn MinutesSource>,
+        grouping: &Grouping,
+    ) -> Result<Self, Error> {
+        let root = Path::new("");
+        let mut overrides = SectionOverrides::read_from_source(source.as_ref(), root)?;
+        // Keys that match no file are only reported for the first section.
+        let other_overrides = overrides.clone();
+        let (dirs, files) = list(&source, root)?;
+        overrides.match_files(&files);
+        let mut images = vec![];
+        let mut ignored_files: Vec<_> = dirs.iter().map(|d| source.root().join(d)).collect();
+        for file in files {
+            if !is_supported_image(file.path()) {
+                ignored_files.push(file.path().to_path_buf());
+            } else if !overrides.for_image(&file).skip {
+                let capture_time = read_capture_time(&file)?;
+                images.push(DatedImage {
+                    image: file,
+                    capture_time,
+                });
+            }
+        }
+        ignored_files.sort();
+        let sections = group_images(images, grouping)
+            .into_iter()
+            .enumerate()
+            .map(|(index, (name, image_files))| Section {
+                name,
+                image_files,
+                overrides: if index == 0 {
+                    overrides.clone()
+                } else {
+                    other_overrides.clone()
+                },
+                subsections: vec![],
+            })
+            .collect();
+        Ok(Self {
+            sections,
+            ignored_files,
+        })
+    }
 }
 
 impl TryFrom<&Path> for Minutes {
-    type Error = anyhow::Error;
+    type Error = Error;
 
     fn try_from(value: &Path) -> Result<Self, Self::Error> {
-        let dir = fs_err::read_dir(value)?;
-        let names = dir.map(|e| Section::try_from(e?));
-        anyhow::Ok(Self {
-            sections: names.collect::<anyhow::Result<Vec<_>>>()?,
-        })
+        Self::read(value, &InputOptions::default())
     }
 }
 
@@ -25,32 +146,88 @@ impl TryFrom<&Path> for Minutes {
 pub struct Section {
     pub name: String,
     pub image_files: Vec<SourceImagePath>,
+    pub overrides: SectionOverrides,
+    pub subsections: Vec<Section>,
+}
+
+impl Section {
+    /// Reads the section of directory `dir` of `source` including its subsections.
+    pub fn read(source: &Arc<dyn MinutesSource>, dir: &Path) -> Result<Self, Error> {
+        let name = dir
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let mut overrides = SectionOverrides::read_from_source(source.as_ref(), dir)
+            .map_err(|e| e.in_section(&name))?;
+        let (dirs, files) = list(source, dir).map_err(|e| e.in_section(&name))?;
+        overrides.match_files(&files);
+        let mut subsections = dirs
+            .iter()
+            .map(|dir| Section::read(source, dir))
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut image_files: Vec<_> = files
+            .into_iter()
+            .filter(|image| !overrides.for_image(image).skip)
+            .collect();
+        image_files.sort_by(|a, b| a.path().cmp(b.path()));
+        subsections.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Section {
+            name,
+            image_files,
+            overrides,
+            subsections,
+        })
+    }
 }
 
 impl TryFrom<fs_err::DirEntry> for Section {
-    type Error = anyhow::Error;
+    type Error = Error;
 
     fn try_from(value: fs_err::DirEntry) -> Result<Self, Self::Error> {
-        let dir = fs_err::read_dir(value.path())?;
-        let image_files = dir
-            .map(|e| anyhow::Ok(SourceImagePath::new(e?.path())))
-            .collect::<anyhow::Result<Vec<_>>>()?;
-        anyhow::Ok(Section {
-            name: value.file_name().to_string_lossy().to_string(),
-            image_files,
-        })
+        let path = value.path();
+        let parent = path.parent().unwrap_or(Path::new("")).to_path_buf();
+        let source: Arc<dyn MinutesSource> = Arc::new(FileSystemSource::new(parent));
+        Section::read(&source, Path::new(&value.file_name()))
     }
 }
 
+/// Subdirectories and files of `dir` except for the overrides and config files.
+fn list(
+    source: &Arc<dyn MinutesSource>,
+    dir: &Path,
+) -> Result<(Vec<PathBuf>, Vec<SourceImagePath>), Error> {
+    let listing = source.list(dir)?;
+    let files = listing
+        .files
+        .into_iter()
+        .filter(|file| {
+            ![OVERRIDES_FILE_NAME, CONFIG_FILE_NAME]
+                .iter()
+                .any(|name| file.file_name() == Some(name.as_ref()))
+        })
+        .map(|file| SourceImagePath::in_source(source.clone(), file))
+        .collect();
+    Ok((listing.dirs, files))
+}
+
 #[cfg(test)]
 mod tests {
     use std::fs;
     use std::fs::create_dir;
+    use std::io::Write;
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
 
     use speculoos::prelude::*;
 
+    use chrono::Duration;
+
+    use crate::config::CONFIG_FILE_NAME;
+    use crate::grouping::Grouping;
     use crate::images::SourceImagePath;
-    use crate::input::{Minutes, Section};
+    use crate::input::{InputOptions, Minutes, Section};
+    use crate::overrides::{SectionOverrides, OVERRIDES_FILE_NAME};
+    use crate::source::InMemorySource;
 
     #[test]
     fn minutes_from_non_existing_parent_dir_is_err() -> anyhow::Result<()> {
@@ -83,6 +260,8 @@ mod tests {
         assert_that!(minutes.sections).contains_all_of(&vec![&Section {
             name: "abc".to_string(),
             image_files: vec![],
+            overrides: SectionOverrides::default(),
+            subsections: vec![],
         }]);
         Ok(())
     }
@@ -101,10 +280,121 @@ mod tests {
         assert_that!(minutes.sections).contains_all_of(&vec![&Section {
             name: "abc".to_string(),
             image_files: vec![image_path],
+            overrides: SectionOverrides::default(),
+            subsections: vec![],
+        }]);
+        Ok(())
+    }
+
+    #[test]
+    fn overrides_file_is_read_and_skipped_images_are_left_out() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let section_path = dir.path().join("abc");
+        create_dir(&section_path)?;
+        let kept_image_path = SourceImagePath::new(section_path.join("1.jpg"));
+        fs::File::create(kept_image_path.path())?;
+        fs::File::create(section_path.join("2.jpg"))?;
+        let overrides_content = "[\"1.jpg\"]\nrotate = 90\n\n[\"2.jpg\"]\nskip = true\n";
+        fs::write(section_path.join(OVERRIDES_FILE_NAME), overrides_content)?;
+
+        let minutes = Minutes::try_from(dir.path())?;
+
+        assert_that!(minutes.sections).contains_all_of(&vec![&Section {
+            name: "abc".to_string(),
+            image_files: vec![kept_image_path],
+            overrides: SectionOverrides::parse(overrides_content)?,
+            subsections: vec![],
+        }]);
+        Ok(())
+    }
+
+    #[test]
+    fn sections_and_images_are_sorted_by_name() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        for section in ["b", "a"] {
+            let section_path = dir.path().join(section);
+            create_dir(&section_path)?;
+            for image in ["2.jpg", "1.jpg", "3.jpg"] {
+                fs::File::create(section_path.join(image))?;
+            }
+        }
+
+        let minutes = Minutes::try_from(dir.path())?;
+
+        let names: Vec<_> = minutes.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_that!(names).is_equal_to(vec!["a", "b"]);
+        let images: Vec<_> = minutes.sections[0]
+            .image_files
+            .iter()
+            .map(|i| i.file_stem().unwrap())
+            .collect();
+        assert_that!(images).is_equal_to(vec!["1".to_string(), "2".to_string(), "3".to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn nested_directories_become_subsections() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let session_path = dir.path().join("track").join("session");
+        fs::create_dir_all(&session_path)?;
+        let image_path = SourceImagePath::new(session_path.join("1.jpg"));
+        fs::File::create(image_path.path())?;
+
+        let minutes = Minutes::try_from(dir.path())?;
+
+        assert_that!(minutes.sections).is_equal_to(vec![Section {
+            name: "track".to_string(),
+            image_files: vec![],
+            overrides: SectionOverrides::default(),
+            subsections: vec![Section {
+                name: "session".to_string(),
+                image_files: vec![image_path],
+                overrides: SectionOverrides::default(),
+                subsections: vec![],
+            }],
         }]);
         Ok(())
     }
 
+    #[test]
+    fn root_images_are_collected_in_an_introduction_section_first() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        create_dir(dir.path().join("abc"))?;
+        let image_path = SourceImagePath::new(dir.path().join("group.JPG"));
+        fs::File::create(image_path.path())?;
+
+        let minutes = Minutes::read(
+            dir.path(),
+            &InputOptions {
+                introduction_title: "Welcome".to_string(),
+            },
+        )?;
+
+        assert_that!(minutes.sections[0]).is_equal_to(&Section {
+            name: "Welcome".to_string(),
+            image_files: vec![image_path],
+            overrides: SectionOverrides::default(),
+            subsections: vec![],
+        });
+        assert_that!(minutes.sections[1].name).is_equal_to("abc".to_string());
+        Ok(())
+    }
+
+    #[test]
+    fn other_root_files_are_ignored_and_reported() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        create_dir(dir.path().join("abc"))?;
+        let notes_path = dir.path().join("notes.txt");
+        fs::File::create(&notes_path)?;
+        fs::File::create(dir.path().join(CONFIG_FILE_NAME))?;
+
+        let minutes = Minutes::try_from(dir.path())?;
+
+        assert_that!(minutes.sections).has_length(1);
+        assert_that!(minutes.ignored_files).is_equal_to(vec![notes_path]);
+        Ok(())
+    }
+
     #[test]
     fn image_paths_are_absolute() -> anyhow::Result<()> {
         let dir = tempfile::tempdir()?;
@@ -119,4 +409,73 @@ mod tests {
         assert_that!(paths.all(|p| p.path().is_absolute())).is_true();
         Ok(())
     }
+
+    #[test]
+    fn flat_directory_without_capture_times_has_an_undated_section() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        create_dir(dir.path().join("abc"))?;
+        let image_path = SourceImagePath::new(dir.path().join("1.jpg"));
+        fs::File::create(image_path.path())?;
+        fs::File::create(dir.path().join("2.jpg"))?;
+        fs::write(
+            dir.path().join(OVERRIDES_FILE_NAME),
+            "[\"2.jpg\"]\nskip = true\n",
+        )?;
+
+        let minutes = Minutes::read_flat(dir.path(), &Grouping::Gap(Duration::minutes(30)))?;
+
+        assert_that!(minutes.sections).has_length(1);
+        assert_that!(minutes.sections[0].name).is_equal_to("Undated".to_string());
+        assert_that!(minutes.sections[0].image_files).is_equal_to(vec![image_path]);
+        assert_that!(minutes.ignored_files).is_equal_to(vec![dir.path().join("abc")]);
+        Ok(())
+    }
+
+    #[test]
+    fn minutes_can_be_read_from_a_zip_archive() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let archive_path = dir.path().join("photos.zip");
+        let mut zip = zip::ZipWriter::new(fs::File::create(&archive_path)?);
+        for (name, content) in [
+            ("minutes/group.jpg", ""),
+            ("minutes/session/1.jpg", ""),
+            ("minutes/session/2.jpg", ""),
+            (
+                "minutes/session/overrides.toml",
+                "[\"2.jpg\"]\nskip = true\n",
+            ),
+        ] {
+            zip.start_file(name, zip::write::SimpleFileOptions::default())?;
+            zip.write_all(content.as_bytes())?;
+        }
+        zip.finish()?;
+
+        let minutes = Minutes::try_from(archive_path.as_path())?;
+
+        let names: Vec<_> = minutes.sections.iter().map(|s| s.name.as_str()).collect();
+        assert_that!(names).is_equal_to(vec!["General", "session"]);
+        let image_paths: Vec<_> = minutes.sections[1]
+            .image_files
+            .iter()
+            .map(|i| i.path().to_path_buf())
+            .collect();
+        assert_that!(image_paths).is_equal_to(vec![archive_path.join("session").join("1.jpg")]);
+        Ok(())
+    }
+
+    #[test]
+    fn minutes_can_be_read_from_an_in_memory_source() -> anyhow::Result<()> {
+        let mut source = InMemorySource::new(PathBuf::from("upload"));
+        source.insert("session/1.jpg", "");
+        source.insert("session/notes/2.jpg", "");
+
+        let minutes = Minutes::read_from_source(Arc::new(source), &InputOptions::default())?;
+
+        assert_that!(minutes.sections).has_length(1);
+        let section = &minutes.sections[0];
+        assert_that!(section.name).is_equal_to("session".to_string());
+        assert_that!(section.image_files[0].path()).is_equal_to(Path::new("upload/session/1.jpg"));
+        assert_that!(section.subsections[0].name).is_equal_to("notes".to_string());
+        Ok(())
+    }
 }
@@ -1,12 +1,37 @@
+pub mod archive;
+pub mod atomic_file;
+pub mod capture_time;
+pub mod config;
 pub mod conversion;
+pub mod error;
+pub mod grouping;
+pub mod html_output;
 pub mod image_operations;
 pub mod images;
 pub mod input;
+pub mod manifest;
 pub mod markdown_output;
+pub mod naming;
 pub mod output;
+pub mod overrides;
+pub mod plan;
+pub mod preview;
+pub mod progress;
+pub mod publish;
+pub mod scaffold;
+pub mod source;
+pub mod validation;
+pub mod verify;
+pub mod watch;
+
+use std::collections::BTreeMap;
+
+pub use error::{Error, Result};
 
 #[derive(Eq, PartialEq, Debug, Clone)]
 pub struct OutputImageFiles {
-    pub small_image: String,
-    pub large_image: String,
+    /// Online path of each generated image by variant name.
+    pub variants: BTreeMap<String, String>,
+    /// Width and height of each generated image by variant name, if known.
+    pub dimensions: BTreeMap<String, (u32, u32)>,
 }
@@ -1,71 +1,527 @@
-use lib::OutputImageFiles;
+use std::collections::{BTreeMap, HashSet};
+use std::io::Write;
+use std::path::Path;
+use std::process::ExitCode;
+
+use cmdparams::{Command, Writing};
+use lib::atomic_file;
+use lib::config::{Config, DocumentSettings, InputSettings, OutputFormat};
+use lib::conversion::{create_minutes_for_conversion, MinutesForConversion, OutputLayout};
+use lib::html_output::{self, HtmlOptions};
+use lib::image_operations::{Sizing, Variant};
+use lib::input::Minutes;
+use lib::manifest::Manifest;
+use lib::markdown_output::{self, MarkdownOptions};
+use lib::output::{create_minutes_for_output, MinutesForOutput};
+use lib::plan::{online_urls, ConversionPlan, ConversionReport, ExistingFiles, FailedImage};
+use lib::preview::{error_html, markdown_to_html, PreviewServer};
+use lib::progress::{format_bytes, format_stats, ProgressPrinter};
+use lib::publish::PublishTo;
+use lib::validation::validate;
+use lib::verify::{verify, VerifyOptions};
+use lib::watch::{ConvertedImages, InputWatcher};
 use markdown_photo_minutes_generator as lib;
-use markdown_photo_minutes_generator::input::Minutes;
-use markdown_photo_minutes_generator::markdown_output;
-use markdown_photo_minutes_generator::output::{MinutesForOutput, SectionForOutput};
 
-fn main() -> anyhow::Result<()> {
-    let options = cmdparams::options().run();
+/// Exit code when some images could not be converted with `--keep-going`.
+const PARTIAL_FAILURE: u8 = 2;
+
+fn main() -> anyhow::Result<ExitCode> {
+    match cmdparams::options().run() {
+        Command::Build(args) => {
+            let writing = args.writing;
+            build(&args.load()?, writing)
+        }
+        Command::Convert(args) => {
+            let writing = args.writing;
+            convert(&args.load()?, writing)
+        }
+        Command::Render(args) => render(&args.load()?),
+        Command::RenderFromManifest(manifest, args) => {
+            render_from_manifest(&args.load()?, &manifest)
+        }
+        Command::Serve(port, args) => serve(&args.load()?, port),
+        Command::Publish(args) => publish(&args.load()?),
+        Command::Verify(base_url, args) => verify_document(&args.load()?, base_url),
+        Command::Check(args) => check(&args.load()?),
+        Command::Init(dir) => init(&dir),
+        Command::PrintConfig(args) => {
+            print!("{}", args.load()?.to_toml()?);
+            Ok(ExitCode::SUCCESS)
+        }
+    }
+}
+
+fn build(config: &Config, writing: Writing) -> anyhow::Result<ExitCode> {
+    let input = config.input_settings()?;
+    let layout = config.output_layout()?;
+    let document = config.document_settings(&layout.variants)?;
+    if writing.watch && !writing.dry_run {
+        return watch(
+            config,
+            |for_conversion| {
+                print_document(for_conversion, &layout, document.clone())?;
+                publish_if_configured(config, for_conversion, &layout, &document)
+            },
+            |err| eprintln!("Error: {:#}", err),
+        );
+    }
+    let minutes = read_minutes(&input)?;
+    let mut for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+    let plan = ConversionPlan::create(
+        &for_conversion,
+        &layout,
+        config.existing.unwrap_or_default(),
+    );
+    if writing.dry_run {
+        let for_output = create_minutes_for_output(&for_conversion, &document.online_base_path)?;
+        print!(
+            "{}",
+            plan.describe(&online_urls(&for_conversion, &for_output))?
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+    let report = convert_images(&plan, &layout, writing.keep_going)?;
+    record_conversion(&mut for_conversion, &report);
+    print_document(&for_conversion, &layout, document.clone())?;
+    publish_if_configured(config, &for_conversion, &layout, &document)?;
+    Ok(exit_code(&report.failed))
+}
+
+fn convert(config: &Config, writing: Writing) -> anyhow::Result<ExitCode> {
+    if writing.watch && !writing.dry_run {
+        return watch(config, |_| Ok(()), |err| eprintln!("Error: {:#}", err));
+    }
+    let input = config.input_settings()?;
+    let layout = config.output_layout()?;
+    let minutes = read_minutes(&input)?;
+    let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+    let plan = ConversionPlan::create(
+        &for_conversion,
+        &layout,
+        config.existing.unwrap_or_default(),
+    );
+    if writing.dry_run {
+        print!("{}", plan.describe(&BTreeMap::new())?);
+        return Ok(ExitCode::SUCCESS);
+    }
+    let report = convert_images(&plan, &layout, writing.keep_going)?;
+    Ok(exit_code(&report.failed))
+}
+
+/// Creates the document for images converted before, e.g. with `convert`.
+fn render(config: &Config) -> anyhow::Result<ExitCode> {
+    let input = config.input_settings()?;
+    let layout = config.output_layout()?;
+    let document = config.document_settings(&layout.variants)?;
+    let minutes = read_minutes(&input)?;
+    let mut for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+    require_converted(&for_conversion)?;
+    let dimensions = for_conversion.read_dimensions()?;
+    for_conversion.record_dimensions(&dimensions);
+    print_document(&for_conversion, &layout, document)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Uploads the images converted before, and the document if it is written below
+/// OUTPUT, to the configured target.
+fn publish(config: &Config) -> anyhow::Result<ExitCode> {
+    let Some(publish_to) = &config.publish_to else {
+        anyhow::bail!("no target given with `--publish-to` or `publish-to` in the config");
+    };
+    let input = config.input_settings()?;
+    let layout = config.output_layout()?;
+    let document = config.document_settings(&layout.variants)?;
+    let minutes = read_minutes(&input)?;
+    let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+    require_converted(&for_conversion)?;
+    publish_output(publish_to, &for_conversion, &layout, &document)?;
+    Ok(ExitCode::SUCCESS)
+}
+
+fn require_converted(for_conversion: &MinutesForConversion) -> anyhow::Result<()> {
+    if let Some(missing) = for_conversion
+        .image_files()
+        .into_iter()
+        .flat_map(|f| f.variants.values())
+        .find(|path| !path.is_file())
+    {
+        anyhow::bail!(
+            "{} does not exist, convert the images first",
+            missing.to_string_lossy()
+        );
+    }
+    Ok(())
+}
+
+fn publish_if_configured(
+    config: &Config,
+    for_conversion: &MinutesForConversion,
+    layout: &OutputLayout,
+    document: &DocumentSettings,
+) -> anyhow::Result<()> {
+    match &config.publish_to {
+        Some(publish_to) => publish_output(publish_to, for_conversion, layout, document),
+        None => Ok(()),
+    }
+}
+
+/// Uploads the changed files of OUTPUT and checks that all image URLs of the document
+/// refer to published files.
+fn publish_output(
+    publish_to: &PublishTo,
+    for_conversion: &MinutesForConversion,
+    layout: &OutputLayout,
+    document: &DocumentSettings,
+) -> anyhow::Result<()> {
+    eprintln!("publishing to {}", publish_to);
+    let for_output = create_minutes_for_output(for_conversion, &document.online_base_path)?;
+    let urls: Vec<String> = online_urls(for_conversion, &for_output)
+        .into_values()
+        .collect();
+    let mut target = publish_to.open()?;
+    let report = lib::publish::publish(
+        &layout.output_root,
+        &mut *target,
+        &document.online_base_path,
+        &urls,
+    )?;
+    eprintln!(
+        "uploaded {} files ({}), {} unchanged",
+        report.uploaded.len(),
+        format_bytes(report.bytes),
+        report.unchanged
+    );
+    Ok(())
+}
+
+/// Builds the minutes and serves them on localhost until the process is stopped,
+/// building them again whenever the input changes. Without OUTPUT the images are
+/// written to a temporary directory. Images converted before are kept unless
+/// `--existing overwrite` is configured.
+fn serve(config: &Config, port: u16) -> anyhow::Result<ExitCode> {
+    let temp_dir = config
+        .output
+        .is_none()
+        .then(tempfile::tempdir)
+        .transpose()?;
+    let output_root = match &temp_dir {
+        Some(dir) => dir.path().to_path_buf(),
+        None => config.output.clone().unwrap_or_default(),
+    };
+    let server = PreviewServer::bind(port, &output_root)?;
+    let url = server.url();
+    let config = Config {
+        output: Some(output_root),
+        online_base_path: Some(url.clone()),
+        existing: Some(match config.existing {
+            Some(ExistingFiles::Overwrite) => ExistingFiles::Overwrite,
+            _ => ExistingFiles::Skip,
+        }),
+        ..config.clone()
+    };
+    let layout = config.output_layout()?;
+    let document = config.document_settings(&layout.variants)?;
+    let page = server.page();
+    std::thread::spawn(move || server.run());
+    eprintln!("serving the minutes at {}", url);
+    watch(
+        &config,
+        |for_conversion| {
+            page.update(preview_body(for_conversion, &layout, &document)?);
+            Ok(())
+        },
+        |err| {
+            eprintln!("Error: {:#}", err);
+            page.update(error_html(&format!("{:#}", err)));
+        },
+    )
+}
+
+/// The document as HTML. Also writes the document and manifest files, if configured.
+fn preview_body(
+    for_conversion: &MinutesForConversion,
+    layout: &OutputLayout,
+    document: &DocumentSettings,
+) -> anyhow::Result<String> {
+    if document.path.is_some() || document.manifest.is_some() {
+        print_document(for_conversion, layout, document.clone())?;
+    }
+    let for_output = create_minutes_for_output(for_conversion, &document.online_base_path)?;
+    let text = create_document(for_output, &layout.variants, document.clone())?;
+    Ok(match document.format {
+        OutputFormat::Markdown => markdown_to_html(&text),
+        OutputFormat::Html => text,
+    })
+}
+
+/// Converts the images and passes the minutes to `on_change`, then converts the images
+/// that were added or changed whenever INPUT changes and passes the minutes again,
+/// until the process is stopped. Images that cannot be converted are left out as with
+/// `--keep-going`, other errors are passed to `on_error` and do not end the loop.
+fn watch(
+    config: &Config,
+    mut on_change: impl FnMut(&MinutesForConversion) -> anyhow::Result<()>,
+    on_error: impl Fn(anyhow::Error),
+) -> anyhow::Result<ExitCode> {
+    let input = config.input_settings()?;
+    let layout = config.output_layout()?;
+    let watcher = InputWatcher::new(&input.root_path)?;
+    let mut converted = ConvertedImages::default();
+    loop {
+        if let Err(err) = convert_changes(config, &input, &layout, &mut converted, &mut on_change) {
+            on_error(err);
+        }
+        watcher.wait()?;
+    }
+}
+
+fn convert_changes(
+    config: &Config,
+    input: &InputSettings,
+    layout: &OutputLayout,
+    converted: &mut ConvertedImages,
+    on_change: &mut impl FnMut(&MinutesForConversion) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let minutes = read_minutes(input)?;
+    let mut for_conversion = create_minutes_for_conversion(&minutes, layout)?;
+    let changes = converted.update(&for_conversion)?;
+    for path in &changes.removed {
+        eprintln!("removed {}", path.to_string_lossy());
+    }
+    let existing = converted.existing_files(config.existing.unwrap_or_default());
+    let mut plan = ConversionPlan::create(&for_conversion, layout, existing);
+    plan.files
+        .retain(|f| changes.changed.contains(f.source_image_path.path()));
+    let report = convert_images(&plan, layout, true)?;
+    converted.record(changes, &report);
+    converted.apply(&mut for_conversion);
+    on_change(&for_conversion)
+}
+
+/// Reads every image and, if variants are configured, checks the generated file names.
+/// Fails if any problems were found.
+fn check(config: &Config) -> anyhow::Result<ExitCode> {
+    let input = config.input_settings()?;
+    let layout = OutputLayout {
+        output_root: config.output.clone().unwrap_or_default(),
+        variants: if config.thumbnail_ratio.is_some() || config.variants.is_some() {
+            config.variants()?
+        } else {
+            vec![]
+        },
+        naming: config.naming.clone().unwrap_or_default(),
+    };
+    let minutes = read_minutes(&input)?;
+    let report = validate(&minutes, &layout);
+    if !report.is_ok() {
+        eprint!("{}", report);
+        anyhow::bail!(
+            "found {} problems in {} images",
+            report.problem_count(),
+            report.image_count
+        );
+    }
+    println!(
+        "{} images in {} sections are fine",
+        report.image_count, report.section_count
+    );
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Checks the links of the document written before against the files in OUTPUT and,
+/// with `base_url`, against the server. Fails if any problems were found.
+fn verify_document(config: &Config, base_url: Option<String>) -> anyhow::Result<ExitCode> {
+    let Some(document_path) = &config.document else {
+        anyhow::bail!("no document given with `--document` or `document` in the config");
+    };
+    let document = fs_err::read_to_string(document_path)?;
+    let options = VerifyOptions {
+        output_root: config.output_root()?,
+        online_base_path: config.online_base_path()?,
+        other_files: [&config.document, &config.manifest]
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect(),
+        base_url,
+    };
+    let report = verify(
+        &document,
+        config.format.unwrap_or(OutputFormat::Markdown),
+        &options,
+    )?;
+    if !report.is_ok() {
+        eprint!("{}", report);
+        anyhow::bail!(
+            "found {} problems with the {} links of {}",
+            report.problems.len(),
+            report.link_count,
+            document_path.to_string_lossy()
+        );
+    }
+    println!(
+        "{} links of {} refer to existing files",
+        report.link_count,
+        document_path.to_string_lossy()
+    );
+    Ok(ExitCode::SUCCESS)
+}
 
-    println!("input: {}", options.input_root_path.to_string_lossy());
-    println!("output: {}", options.output_root_path.to_string_lossy());
+fn init(dir: &Path) -> anyhow::Result<ExitCode> {
+    for file in lib::scaffold::create_input_scaffold(dir)? {
+        println!("created {}", file.to_string_lossy());
+    }
+    Ok(ExitCode::SUCCESS)
+}
 
-    let minutes = Minutes::try_from(options.input_root_path.as_path())?;
+fn read_minutes(input: &InputSettings) -> anyhow::Result<Minutes> {
+    println!("input: {}", input.root_path.to_string_lossy());
+    let minutes = input.read_minutes()?;
+    for ignored_file in &minutes.ignored_files {
+        eprintln!(
+            "ignoring {}: not a supported image",
+            ignored_file.to_string_lossy()
+        );
+    }
+    Ok(minutes)
+}
 
-    let for_conversion =
-        markdown_photo_minutes_generator::conversion::create_minutes_for_conversion(
-            &minutes,
-            options.output_root_path.as_path(),
-        )?;
+/// Shows the progress and a summary on stderr, including the images that could not be
+/// converted with `keep_going`.
+fn convert_images(
+    plan: &ConversionPlan,
+    layout: &OutputLayout,
+    keep_going: bool,
+) -> anyhow::Result<ConversionReport> {
+    println!("output: {}", layout.output_root.to_string_lossy());
+    let mut progress = ProgressPrinter::stderr();
+    let result = plan.execute(keep_going, |p| progress.update(p));
+    progress.finish();
+    let report = result.map_err(|err| {
+        if matches!(err.cause(), lib::Error::DestinationExists { .. }) {
+            anyhow::anyhow!(
+                "{}, pass `--existing overwrite` or `--existing skip`",
+                err.full_message()
+            )
+        } else {
+            err.into()
+        }
+    })?;
+    eprintln!("{}", format_stats(&report.stats));
+    if !report.failed.is_empty() {
+        eprintln!("could not convert {} images:", report.failed.len());
+        for image in &report.failed {
+            eprintln!("  - {}", image);
+        }
+    }
+    Ok(report)
+}
 
-    if !options.skip_image_conversion {
-        let vec = for_conversion.sections.clone();
-        vec.into_iter()
-            .flat_map(|s| s.image_files)
-            .try_for_each(|f| {
-                lib::image_operations::save_as_resized_image(
-                    f.source_image_path,
-                    f.large_image,
-                    1.0,
-                )?;
-                lib::image_operations::save_as_resized_image(
-                    f.source_image_path,
-                    f.small_image,
-                    options.thumbnail_ratio,
-                )?;
-                anyhow::Ok(())
-            })?;
+/// Leaves out the images that could not be converted and records the dimensions of
+/// the generated files.
+fn record_conversion(for_conversion: &mut MinutesForConversion, report: &ConversionReport) {
+    let failed_paths: HashSet<&Path> = report
+        .failed
+        .iter()
+        .map(|f| f.source_image_path.as_path())
+        .collect();
+    for_conversion.retain_images(|f| !failed_paths.contains(f.source_image_path.path()));
+    for_conversion.record_dimensions(&report.dimensions);
+}
+
+fn exit_code(failed: &[FailedImage]) -> ExitCode {
+    if failed.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(PARTIAL_FAILURE)
     }
+}
 
-    let for_output = into_minutes_for_outputs(&for_conversion, options.online_base_path)?;
+fn print_document(
+    for_conversion: &MinutesForConversion,
+    layout: &OutputLayout,
+    document: DocumentSettings,
+) -> anyhow::Result<()> {
+    let for_output = create_minutes_for_output(for_conversion, &document.online_base_path)?;
+    if let Some(manifest_path) = &document.manifest {
+        Manifest::create(
+            for_conversion,
+            &for_output,
+            layout,
+            &document.online_base_path,
+        )?
+        .write(manifest_path)?;
+    }
+    write_document(for_output, &layout.variants, document)
+}
 
-    let markdown = markdown_output::create_markdown(for_output)?;
+/// Creates the document for the files described by a manifest written before, so
+/// neither the source images nor the input directory are needed. The online base path
+/// of the manifest is used unless another one is given.
+fn render_from_manifest(config: &Config, manifest_path: &Path) -> anyhow::Result<ExitCode> {
+    let mut manifest = Manifest::read(manifest_path)?;
+    let config = config.clone().or(Config {
+        online_base_path: manifest.online_base_path.clone(),
+        ..Config::default()
+    });
+    let document = config.document_settings(&manifest.variants)?;
+    manifest.rebase(&document.online_base_path)?;
+    if let Some(path) = &document.manifest {
+        manifest.write(path)?;
+    }
+    write_document(manifest.minutes_for_output(), &manifest.variants, document)?;
+    Ok(ExitCode::SUCCESS)
+}
 
-    println!("{}", markdown);
+fn write_document(
+    for_output: MinutesForOutput,
+    variants: &[Variant],
+    document: DocumentSettings,
+) -> anyhow::Result<()> {
+    let path = document.path.clone();
+    let document = create_document(for_output, variants, document)?;
+    match path {
+        Some(path) => atomic_file::replace(&path, |file| {
+            writeln!(file, "{}", document).map_err(|source| lib::Error::Write {
+                path: path.clone(),
+                source,
+            })
+        })?,
+        None => println!("{}", document),
+    }
 
     Ok(())
 }
 
-fn into_minutes_for_outputs<'source>(
-    for_conversion: &'source markdown_photo_minutes_generator::conversion::MinutesForConversion,
-    online_base_path: String,
-) -> anyhow::Result<MinutesForOutput<'source>> {
-    Ok(MinutesForOutput {
-        sections: for_conversion
-            .sections
-            .iter()
-            .map(|s| {
-                let image_files: Vec<OutputImageFiles> = s
-                    .image_files
+fn create_document(
+    for_output: MinutesForOutput,
+    variants: &[Variant],
+    document: DocumentSettings,
+) -> anyhow::Result<String> {
+    Ok(match document.format {
+        OutputFormat::Markdown => markdown_output::create_markdown(
+            for_output,
+            &MarkdownOptions {
+                thumbnail_variant: document.thumbnail_variant,
+                link_variant: document.link_variant,
+                html_images: document.html_images,
+            },
+        )?,
+        OutputFormat::Html => html_output::create_html(
+            for_output,
+            &HtmlOptions {
+                thumbnail_variant: document.thumbnail_variant,
+                link_variant: document.link_variant,
+                srcset: variants
                     .iter()
-                    .map(|f| OutputImageFiles::create(f, &online_base_path))
-                    .collect::<anyhow::Result<_>>()?;
-                Ok(SectionForOutput {
-                    name: s.name,
-                    image_files,
-                })
-            })
-            .collect::<anyhow::Result<_>>()?,
+                    .filter_map(|v| match v.resize.sizing {
+                        Sizing::MaxWidth(width) => Some((v.name.clone(), width)),
+                        _ => None,
+                    })
+                    .collect(),
+                sizes: document.sizes,
+            },
+        )?,
     })
 }
 
@@ -73,82 +529,834 @@ mod cmdparams {
     use std::path::PathBuf;
 
     use bpaf::{OptionParser, Parser};
+    use markdown_photo_minutes_generator::config::{Config, OutputFormat};
+    use markdown_photo_minutes_generator::image_operations::{ResamplingFilter, Variant};
+    use markdown_photo_minutes_generator::naming::NamingScheme;
+    use markdown_photo_minutes_generator::plan::ExistingFiles;
+    use markdown_photo_minutes_generator::publish::PublishTo;
 
     #[derive(Clone, Debug)]
-    pub struct ImageConversionOptions {
-        pub input_root_path: PathBuf,
-        pub output_root_path: PathBuf,
-        pub online_base_path: String,
-        pub skip_image_conversion: bool,
-        pub thumbnail_ratio: f32,
-    }
-
-    pub fn options() -> OptionParser<ImageConversionOptions> {
-        let input_root_path = bpaf::positional("INPUT");
-        let output_root_path = bpaf::positional::<PathBuf>("OUTPUT");
-        let online_base_path = bpaf::positional::<String>("ONLINE_BASE_PATH");
-        let skip_image_conversion = bpaf::long("skip-image-conversion").flag(true, false);
-        let thumbnail_ratio = bpaf::long("thumbnail-ratio").argument::<f32>("THUMBNAIL_RATIO");
-
-        bpaf::construct!(ImageConversionOptions {
-            skip_image_conversion,
+    pub enum Command {
+        Build(CliArgs),
+        Convert(CliArgs),
+        Render(CliArgs),
+        RenderFromManifest(PathBuf, CliArgs),
+        /// Port and settings of the preview server.
+        Serve(u16, CliArgs),
+        Publish(CliArgs),
+        /// Base URL to check online and the settings of the document.
+        Verify(Option<String>, CliArgs),
+        Check(CliArgs),
+        Init(PathBuf),
+        PrintConfig(CliArgs),
+    }
+
+    #[derive(Clone, Debug)]
+    pub struct CliArgs {
+        pub config_file: Option<PathBuf>,
+        /// Only set by the commands that write images.
+        pub writing: Writing,
+        pub config: Config,
+    }
+
+    #[derive(Clone, Copy, Debug, Default)]
+    pub struct Writing {
+        pub dry_run: bool,
+        /// Convert the other images if one fails and leave it out of the document.
+        pub keep_going: bool,
+        /// Keep running and convert the images that are added or changed.
+        pub watch: bool,
+    }
+
+    impl CliArgs {
+        /// The command line arguments layered over environment, config file and defaults.
+        pub fn load(self) -> anyhow::Result<Config> {
+            Ok(Config::load(
+                self.config,
+                self.config_file.as_deref(),
+                |key| std::env::var(key),
+            )?)
+        }
+    }
+
+    /// Combines the parts of the command line settings, which set disjoint keys.
+    fn merged<const N: usize>(parts: [Config; N]) -> Config {
+        parts.into_iter().fold(Config::default(), Config::or)
+    }
+
+    fn input() -> impl Parser<Config> {
+        bpaf::positional::<PathBuf>("INPUT")
+            .optional()
+            .map(|input| Config {
+                input,
+                ..Config::default()
+            })
+    }
+
+    fn grouping() -> impl Parser<Config> {
+        let introduction_title = bpaf::long("introduction-title")
+            .help("Heading of the section with the images placed directly in INPUT, empty for none")
+            .argument::<String>("TITLE")
+            .optional();
+        let group_by_gap = bpaf::long("group-by-gap")
+            .help("Treat INPUT as a flat directory of photos and start a new section after a gap of more than MINUTES between capture times")
+            .argument::<u32>("MINUTES")
+            .optional();
+        let schedule = bpaf::long("schedule")
+            .help("Treat INPUT as a flat directory of photos and put them into the slots of the schedule FILE by capture time")
+            .argument::<PathBuf>("FILE")
+            .optional();
+        bpaf::construct!(introduction_title, group_by_gap, schedule).map(
+            |(introduction_title, group_by_gap, schedule)| Config {
+                introduction_title,
+                group_by_gap,
+                schedule,
+                ..Config::default()
+            },
+        )
+    }
+
+    fn images() -> impl Parser<Config> {
+        let thumbnail_ratio = bpaf::long("thumbnail-ratio")
+            .argument::<f32>("THUMBNAIL_RATIO")
+            .optional();
+        let thumbnail_filter = bpaf::long("thumbnail-filter")
+            .help("Resampling filter for thumbnails: nearest, triangle, catmull-rom, gaussian or lanczos3")
+            .argument::<ResamplingFilter>("FILTER")
+            .optional();
+        let large_filter = bpaf::long("large-filter")
+            .help("Resampling filter for enlarged images that have to be re-encoded")
+            .argument::<ResamplingFilter>("FILTER")
+            .optional();
+        let variants = bpaf::long("variant")
+            .help("Image variant to generate instead of small and large, e.g. thumb=320w, medium=1024w:lanczos3 or full=original")
+            .argument::<Variant>("NAME=SIZE[:FILTER]")
+            .many()
+            .map(|variants| (!variants.is_empty()).then_some(variants));
+        let naming = bpaf::long("naming")
+            .help("Template for the generated file names, e.g. {section_slug}/{index:03}_{variant}.{ext_lower} or {hash8}_{variant}.{ext}")
+            .argument::<NamingScheme>("TEMPLATE")
+            .optional();
+        bpaf::construct!(
             thumbnail_ratio,
-            input_root_path,
-            output_root_path,
-            online_base_path,
+            thumbnail_filter,
+            large_filter,
+            variants,
+            naming
+        )
+        .map(
+            |(thumbnail_ratio, thumbnail_filter, large_filter, variants, naming)| Config {
+                thumbnail_ratio,
+                thumbnail_filter,
+                large_filter,
+                variants,
+                naming,
+                ..Config::default()
+            },
+        )
+    }
+
+    fn output() -> impl Parser<Config> {
+        bpaf::positional::<PathBuf>("OUTPUT")
+            .optional()
+            .map(|output| Config {
+                output,
+                ..Config::default()
+            })
+    }
+
+    fn document() -> impl Parser<Config> {
+        let thumbnail_variant = bpaf::long("thumbnail-variant")
+            .help("Variant shown in the document")
+            .argument::<String>("NAME")
+            .optional();
+        let link_variant = bpaf::long("link-variant")
+            .help("Variant the shown images link to")
+            .argument::<String>("NAME")
+            .optional();
+        let format = bpaf::long("format")
+            .help("Format of the document: markdown or html")
+            .argument::<OutputFormat>("FORMAT")
+            .optional();
+        let sizes = bpaf::long("sizes")
+            .help("`sizes` attribute used with `srcset` in HTML output")
+            .argument::<String>("SIZES")
+            .optional();
+        let html_images = bpaf::long("html-images")
+            .help("Show the images in Markdown with HTML tags that carry their width and height")
+            .switch()
+            .map(|html_images| html_images.then_some(true));
+        let document = bpaf::long("document")
+            .help("Write the document to FILE instead of stdout, replacing it only once complete")
+            .argument::<PathBuf>("FILE")
+            .optional();
+        let manifest = bpaf::long("manifest")
+            .help(
+                "Also write a JSON description of the sections, images and generated files to FILE",
+            )
+            .argument::<PathBuf>("FILE")
+            .optional();
+        bpaf::construct!(
+            thumbnail_variant,
+            link_variant,
+            format,
+            sizes,
+            html_images,
+            document,
+            manifest
+        )
+        .map(
+            |(thumbnail_variant, link_variant, format, sizes, html_images, document, manifest)| {
+                Config {
+                    thumbnail_variant,
+                    link_variant,
+                    format,
+                    sizes,
+                    html_images,
+                    document,
+                    manifest,
+                    ..Config::default()
+                }
+            },
+        )
+    }
+
+    fn online_base_path() -> impl Parser<Config> {
+        bpaf::positional::<String>("ONLINE_BASE_PATH")
+            .optional()
+            .map(|online_base_path| Config {
+                online_base_path,
+                ..Config::default()
+            })
+    }
+
+    fn existing() -> impl Parser<Config> {
+        bpaf::long("existing")
+            .help("What to do with output files that exist already: fail (default), overwrite or skip")
+            .argument::<ExistingFiles>("POLICY")
+            .optional()
+            .map(|existing| Config {
+                existing,
+                ..Config::default()
+            })
+    }
+
+    /// Adds `--existing` for the commands that write images and `config print`.
+    fn with_existing(args: impl Parser<CliArgs>) -> impl Parser<CliArgs> {
+        let existing = existing();
+        bpaf::construct!(existing, args).map(|(existing, args)| CliArgs {
+            config: existing.or(args.config),
+            ..args
+        })
+    }
+
+    /// Adds `--dry-run`, `--keep-going`, `--watch` and `--existing` for the commands that
+    /// write images.
+    fn writing(args: impl Parser<CliArgs>) -> impl Parser<CliArgs> {
+        let dry_run = bpaf::long("dry-run")
+            .help("Only print which files would be created, overwritten or skipped, with their sizes and URLs")
+            .switch();
+        let keep_going = bpaf::long("keep-going")
+            .help("Convert the other images if one fails, leave it out of the document and exit with code 2")
+            .switch();
+        let watch = bpaf::long("watch")
+            .help("Keep running, convert the images that are added to or changed in INPUT and update the document")
+            .switch();
+        let args = with_existing(args);
+        bpaf::construct!(dry_run, keep_going, watch, args).map(
+            |(dry_run, keep_going, watch, args)| CliArgs {
+                writing: Writing {
+                    dry_run,
+                    keep_going,
+                    watch,
+                },
+                ..args
+            },
+        )
+    }
+
+    /// Adds `--publish-to` for `build` and `publish`.
+    fn publishing(args: impl Parser<CliArgs>) -> impl Parser<CliArgs> {
+        let publish_to = bpaf::long("publish-to")
+            .help("Upload the changed files of OUTPUT to TARGET: a directory, sftp://[USER@]HOST[:PORT]/PATH or s3://BUCKET[/PREFIX][?endpoint=URL&region=REGION]")
+            .argument::<PublishTo>("TARGET")
+            .optional();
+        bpaf::construct!(publish_to, args).map(|(publish_to, args)| CliArgs {
+            config: Config {
+                publish_to,
+                ..Config::default()
+            }
+            .or(args.config),
+            ..args
         })
-        .to_options()
+    }
+
+    fn config_file() -> impl Parser<Option<PathBuf>> {
+        bpaf::long("config")
+            .help("Config file to use instead of minutes.toml in INPUT")
+            .argument::<PathBuf>("FILE")
+            .optional()
+    }
+
+    /// Input, images and document settings as used by `build`, `render` and `config print`.
+    fn all_args() -> impl Parser<CliArgs> {
+        let config_file = config_file();
+        let grouping = grouping();
+        let images = images();
+        let document = document();
+        let input = input();
+        let output = output();
+        let online_base_path = online_base_path();
+        bpaf::construct!(
+            config_file,
+            grouping,
+            images,
+            document,
+            input,
+            output,
+            online_base_path
+        )
+        .map(
+            |(config_file, grouping, images, document, input, output, online_base_path)| CliArgs {
+                config_file,
+                writing: Writing::default(),
+                config: merged([grouping, images, document, input, output, online_base_path]),
+            },
+        )
+    }
+
+    /// Document settings for `render --from-manifest`, which needs no INPUT or OUTPUT.
+    fn manifest_args() -> impl Parser<(PathBuf, CliArgs)> {
+        let config_file = config_file();
+        let manifest = bpaf::long("from-manifest")
+            .help("Render from the manifest FILE written by an earlier run instead of from INPUT")
+            .argument::<PathBuf>("FILE");
+        let document = document();
+        let online_base_path = online_base_path();
+        bpaf::construct!(config_file, manifest, document, online_base_path).map(
+            |(config_file, manifest, document, online_base_path)| {
+                (
+                    manifest,
+                    CliArgs {
+                        config_file,
+                        writing: Writing::default(),
+                        config: merged([document, online_base_path]),
+                    },
+                )
+            },
+        )
+    }
+
+    fn convert_args() -> impl Parser<CliArgs> {
+        let config_file = config_file();
+        let grouping = grouping();
+        let images = images();
+        let input = input();
+        let output = output();
+        bpaf::construct!(config_file, grouping, images, input, output).map(
+            |(config_file, grouping, images, input, output)| CliArgs {
+                config_file,
+                writing: Writing::default(),
+                config: merged([grouping, images, input, output]),
+            },
+        )
+    }
+
+    /// Like [`all_args`], but the online base path is the address of the preview server.
+    fn serve_args() -> impl Parser<(u16, CliArgs)> {
+        let port = bpaf::long("port")
+            .help("Port of localhost to serve the preview on")
+            .argument::<u16>("PORT")
+            .fallback(8000)
+            .display_fallback();
+        let config_file = config_file();
+        let grouping = grouping();
+        let images = images();
+        let document = document();
+        let existing = existing();
+        let input = input();
+        let output = output();
+        bpaf::construct!(
+            port,
+            config_file,
+            grouping,
+            images,
+            document,
+            existing,
+            input,
+            output
+        )
+        .map(
+            |(port, config_file, grouping, images, document, existing, input, output)| {
+                (
+                    port,
+                    CliArgs {
+                        config_file,
+                        writing: Writing::default(),
+                        config: merged([grouping, images, document, existing, input, output]),
+                    },
+                )
+            },
+        )
+    }
+
+    /// The document and where its links refer to.
+    fn verify_args() -> impl Parser<(Option<String>, CliArgs)> {
+        let base_url = bpaf::long("base-url")
+            .help("Also request every image with HEAD below URL, e.g. where OUTPUT was published")
+            .argument::<String>("URL")
+            .optional();
+        let config_file = config_file();
+        let document = document();
+        let input = input();
+        let output = output();
+        let online_base_path = online_base_path();
+        bpaf::construct!(
+            base_url,
+            config_file,
+            document,
+            input,
+            output,
+            online_base_path
+        )
+        .map(
+            |(base_url, config_file, document, input, output, online_base_path)| {
+                (
+                    base_url,
+                    CliArgs {
+                        config_file,
+                        writing: Writing::default(),
+                        config: merged([document, input, output, online_base_path]),
+                    },
+                )
+            },
+        )
+    }
+
+    fn check_args() -> impl Parser<CliArgs> {
+        let config_file = config_file();
+        let grouping = grouping();
+        let images = images();
+        let input = input();
+        bpaf::construct!(config_file, grouping, images, input).map(
+            |(config_file, grouping, images, input)| CliArgs {
+                config_file,
+                writing: Writing::default(),
+                config: merged([grouping, images, input]),
+            },
+        )
+    }
+
+    pub fn options() -> OptionParser<Command> {
+        let build = writing(publishing(all_args()))
+            .map(Command::Build)
+            .to_options()
+            .descr("Convert the images and print the document")
+            .command("build");
+        let convert = writing(convert_args())
+            .map(Command::Convert)
+            .to_options()
+            .descr("Only convert the images")
+            .command("convert");
+        let from_manifest =
+            manifest_args().map(|(manifest, args)| Command::RenderFromManifest(manifest, args));
+        let from_input = all_args().map(Command::Render);
+        let render = bpaf::construct!([from_manifest, from_input])
+            .to_options()
+            .descr("Only print the document for images converted before")
+            .command("render");
+        let serve = serve_args()
+            .map(|(port, args)| Command::Serve(port, args))
+            .to_options()
+            .descr("Build the minutes into OUTPUT or a temporary directory and serve them on localhost, rebuilding them when INPUT changes")
+            .command("serve");
+        let publish = publishing(all_args())
+            .map(Command::Publish)
+            .to_options()
+            .descr("Upload the images converted before to the publish target and check that every image URL of the document resolves")
+            .command("publish");
+        let verify = verify_args()
+            .map(|(base_url, args)| Command::Verify(base_url, args))
+            .to_options()
+            .descr("Check that the image links of the document refer to files in OUTPUT and that every file there is linked")
+            .command("verify");
+        let check = check_args()
+            .map(Command::Check)
+            .to_options()
+            .descr("Check that all images of INPUT can be read and get distinct file names")
+            .command("check");
+        let init = bpaf::positional::<PathBuf>("DIR")
+            .map(Command::Init)
+            .to_options()
+            .descr("Create an input directory with an example config and overrides file")
+            .command("init");
+        let print = with_existing(publishing(all_args()))
+            .map(Command::PrintConfig)
+            .to_options()
+            .descr("Print the effective configuration merged from command line, environment (MINUTES_*), config file and defaults")
+            .command("print");
+        let config = bpaf::construct!([print])
+            .to_options()
+            .descr("Inspect the configuration")
+            .command("config");
+        bpaf::construct!([build, convert, render, serve, publish, verify, check, init, config])
+            .to_options()
     }
 
     #[cfg(test)]
     mod tests {
-        use super::options;
+        use super::{options, CliArgs, Command};
+        use chrono::Duration;
+        use markdown_photo_minutes_generator::config::{Config, OutputFormat};
+        use markdown_photo_minutes_generator::grouping::Grouping;
+        use markdown_photo_minutes_generator::image_operations::{
+            ResamplingFilter, ResizeOptions, Sizing, Variant,
+        };
+        use markdown_photo_minutes_generator::plan::ExistingFiles;
+        use markdown_photo_minutes_generator::publish::PublishTo;
         use speculoos::prelude::*;
         use std::path::PathBuf;
 
+        fn build_args(args: &[&str]) -> anyhow::Result<Config> {
+            let args: Vec<_> = ["build"].iter().chain(args).copied().collect();
+            match options().run_inner(&args[..]) {
+                Ok(Command::Build(CliArgs { config, .. })) => Ok(config.or(Config::defaults())),
+                Ok(command) => anyhow::bail!("unexpected command {:?}", command),
+                Err(failure) => anyhow::bail!("{:?}", failure),
+            }
+        }
+
+        #[test]
+        fn options_parsing_works() -> anyhow::Result<()> {
+            let config = build_args(&[
+                "--thumbnail-ratio",
+                "0.3",
+                "/a",
+                "/b",
+                "http://localhost/output",
+            ])?;
+
+            let layout = config.output_layout()?;
+            let document = config.document_settings(&layout.variants)?;
+            assert_that!(config.input_settings()?.root_path).is_equal_to(PathBuf::from("/a"));
+            assert_that!(layout.output_root).is_equal_to(PathBuf::from("/b"));
+            assert_that!(document.online_base_path)
+                .is_equal_to("http://localhost/output".to_string());
+            assert_that!(layout.variants).is_equal_to(vec![
+                Variant {
+                    name: "small".to_string(),
+                    resize: ResizeOptions {
+                        sizing: Sizing::Ratio(0.3),
+                        filter: ResamplingFilter::Triangle,
+                    },
+                },
+                Variant {
+                    name: "large".to_string(),
+                    resize: ResizeOptions {
+                        sizing: Sizing::Ratio(1.0),
+                        filter: ResamplingFilter::Triangle,
+                    },
+                },
+            ]);
+            assert_that!(document.format).is_equal_to(OutputFormat::Markdown);
+            Ok(())
+        }
+
+        #[test]
+        fn resampling_filters_can_be_selected_per_variant() -> anyhow::Result<()> {
+            let variants = build_args(&[
+                "--thumbnail-ratio",
+                "0.3",
+                "--thumbnail-filter",
+                "lanczos3",
+                "--large-filter",
+                "catmull-rom",
+                "/a",
+                "/b",
+                "http://localhost/output",
+            ])?
+            .variants()?;
+
+            assert_that!(variants[0].resize.filter).is_equal_to(ResamplingFilter::Lanczos3);
+            assert_that!(variants[1].resize.filter).is_equal_to(ResamplingFilter::CatmullRom);
+            Ok(())
+        }
+
+        #[test]
+        fn custom_variants_replace_small_and_large() -> anyhow::Result<()> {
+            let config = build_args(&[
+                "--variant",
+                "thumb=320w",
+                "--variant",
+                "large=2048w:lanczos3",
+                "--thumbnail-variant",
+                "thumb",
+                "--format",
+                "html",
+                "/a",
+                "/b",
+                "http://localhost/output",
+            ])?;
+
+            let variants = config.variants()?;
+            let document = config.document_settings(&variants)?;
+            let names: Vec<_> = variants.iter().map(|v| v.name.as_str()).collect();
+            assert_that!(names).is_equal_to(vec!["thumb", "large"]);
+            assert_that!(document.thumbnail_variant).is_equal_to("thumb".to_string());
+            assert_that!(document.link_variant).is_equal_to("large".to_string());
+            assert_that!(document.format).is_equal_to(OutputFormat::Html);
+            Ok(())
+        }
+
+        #[test]
+        fn selected_variants_must_be_generated() -> anyhow::Result<()> {
+            let config = build_args(&[
+                "--variant",
+                "thumb=320w",
+                "/a",
+                "/b",
+                "http://localhost/output",
+            ])?;
+
+            let result = config.document_settings(&config.variants()?);
+
+            let err = assert_that!(result).is_err().subject.to_string();
+            assert_that!(err).contains("must be among the generated variants");
+            Ok(())
+        }
+
+        #[test]
+        fn group_by_gap_selects_flat_input() -> anyhow::Result<()> {
+            let config = build_args(&["--group-by-gap", "45", "/a"])?;
+
+            assert_that!(config.input_settings()?.grouping)
+                .is_equal_to(Some(Grouping::Gap(Duration::minutes(45))));
+            Ok(())
+        }
+
         #[test]
-        fn options_parsing_works() {
-            let opts = options()
+        fn thumbnail_ratio_must_be_specified() -> anyhow::Result<()> {
+            let config = build_args(&["/a", "/b", "http://localhost/output"])?;
+
+            let result = config.output_layout();
+
+            let err = assert_that!(result).is_err().subject.to_string();
+            assert_that!(err).contains("expected `--thumbnail-ratio=THUMBNAIL_RATIO`");
+            Ok(())
+        }
+
+        #[test]
+        fn commands_only_take_their_own_arguments() -> anyhow::Result<()> {
+            let convert = options()
+                .run_inner(&["convert", "--thumbnail-ratio", "0.3", "/a", "/b"])
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
+            assert_that!(matches!(convert, Command::Convert(_))).is_true();
+
+            let with_online_base_path =
+                options().run_inner(&["convert", "/a", "/b", "http://localhost"]);
+            assert_that!(with_online_base_path.is_err()).is_true();
+            let with_format = options().run_inner(&["check", "--format", "html", "/a"]);
+            assert_that!(with_format.is_err()).is_true();
+            let render_dry_run = options().run_inner(&["render", "--dry-run", "/a"]);
+            assert_that!(render_dry_run.is_err()).is_true();
+            let without_subcommand = options().run_inner(&["--thumbnail-ratio", "0.3", "/a"]);
+            assert_that!(without_subcommand.is_err()).is_true();
+            Ok(())
+        }
+
+        #[test]
+        fn writing_options_are_parsed_for_writing_commands() -> anyhow::Result<()> {
+            let command = options()
                 .run_inner(&[
-                    "--thumbnail-ratio",
-                    "0.3",
+                    "build",
+                    "--dry-run",
+                    "--keep-going",
+                    "--existing",
+                    "skip",
                     "/a",
-                    "/b",
-                    "http://localhost/output",
                 ])
-                .expect("options should be parsable");
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
 
-            assert_that!(opts.input_root_path).is_equal_to(PathBuf::from("/a"));
-            assert_that!(opts.output_root_path).is_equal_to(PathBuf::from("/b"));
-            assert_that!(opts.skip_image_conversion).is_false();
-            assert_that!(opts.online_base_path).is_equal_to("http://localhost/output".to_string());
-            assert_that!(opts.thumbnail_ratio).is_equal_to(0.3);
+            match command {
+                Command::Build(args) => {
+                    assert_that!(args.writing.dry_run).is_true();
+                    assert_that!(args.writing.keep_going).is_true();
+                    assert_that!(args.config.existing).is_equal_to(Some(ExistingFiles::Skip));
+                }
+                command => panic!("unexpected command {:?}", command),
+            }
+            Ok(())
         }
 
         #[test]
-        fn skip_image_conversion_flag_is_set_to_true_when_specified() {
-            let opts = options()
+        fn render_reads_either_input_or_a_manifest() -> anyhow::Result<()> {
+            let from_manifest = options()
                 .run_inner(&[
-                    "--skip-image-conversion",
-                    "--thumbnail-ratio",
-                    "0.3",
+                    "render",
+                    "--from-manifest",
+                    "/out/manifest.json",
+                    "--format",
+                    "html",
+                    "http://localhost",
+                ])
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
+            let from_input = options()
+                .run_inner(&["render", "/a", "/b", "http://localhost"])
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
+
+            match from_manifest {
+                Command::RenderFromManifest(manifest, args) => {
+                    assert_that!(manifest).is_equal_to(PathBuf::from("/out/manifest.json"));
+                    assert_that!(args.config.format).is_equal_to(Some(OutputFormat::Html));
+                    assert_that!(args.config.online_base_path)
+                        .is_equal_to(Some("http://localhost".to_string()));
+                }
+                command => panic!("unexpected command {:?}", command),
+            }
+            assert_that!(matches!(from_input, Command::Render(_))).is_true();
+            Ok(())
+        }
+
+        #[test]
+        fn build_and_publish_take_a_publish_target() -> anyhow::Result<()> {
+            let config = build_args(&[
+                "--publish-to",
+                "sftp://minutes@example.org/var/www/minutes",
+                "--thumbnail-ratio",
+                "0.3",
+                "/a",
+                "/b",
+                "https://example.org/minutes",
+            ])?;
+            let publish = options()
+                .run_inner(&["publish", "--publish-to", "/srv/www", "/a", "/b", "/"])
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
+            let convert = options().run_inner(&["convert", "--publish-to", "/srv/www", "/a", "/b"]);
+
+            assert_that!(config.publish_to).is_equal_to(Some(PublishTo::Sftp {
+                user: Some("minutes".to_string()),
+                host: "example.org".to_string(),
+                port: None,
+                path: "/var/www/minutes".to_string(),
+            }));
+            match publish {
+                Command::Publish(args) => assert_that!(args.config.publish_to)
+                    .is_equal_to(Some(PublishTo::Directory(PathBuf::from("/srv/www")))),
+                command => panic!("unexpected command {:?}", command),
+            }
+            assert_that!(convert).is_err();
+            Ok(())
+        }
+
+        #[test]
+        fn verify_takes_a_document_and_a_base_url() -> anyhow::Result<()> {
+            let command = options()
+                .run_inner(&[
+                    "verify",
+                    "--base-url",
+                    "https://example.org/minutes",
+                    "--document",
+                    "/minutes.md",
                     "/a",
                     "/b",
-                    "http://localhost/output",
+                    "/minutes",
                 ])
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
+
+            match command {
+                Command::Verify(base_url, CliArgs { config, .. }) => {
+                    assert_that!(base_url)
+                        .is_equal_to(Some("https://example.org/minutes".to_string()));
+                    assert_that!(config.document).is_equal_to(Some(PathBuf::from("/minutes.md")));
+                    assert_that!(config.online_base_path).is_equal_to(Some("/minutes".to_string()));
+                }
+                command => panic!("unexpected command {:?}", command),
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn serve_takes_a_port_and_no_online_base_path() -> anyhow::Result<()> {
+            let command = options()
+                .run_inner(&["serve", "--port", "8080", "--thumbnail-ratio", "0.3", "/a"])
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
+            let with_base_path = options().run_inner(&["serve", "/a", "/b", "http://localhost"]);
+
+            match command {
+                Command::Serve(port, CliArgs { config, .. }) => {
+                    assert_that!(port).is_equal_to(8080);
+                    assert_that!(config.input).is_equal_to(Some(PathBuf::from("/a")));
+                    assert_that!(config.output).is_none();
+                }
+                command => panic!("unexpected command {:?}", command),
+            }
+            assert_that!(with_base_path).is_err();
+            Ok(())
+        }
+
+        #[test]
+        fn convert_can_watch_the_input() -> anyhow::Result<()> {
+            let command = options()
+                .run_inner(&["convert", "--watch", "--thumbnail-ratio", "0.3", "/a", "/b"])
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
+
+            match command {
+                Command::Convert(args) => assert_that!(args.writing.watch).is_true(),
+                command => panic!("unexpected command {:?}", command),
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn init_takes_a_directory() -> anyhow::Result<()> {
+            let command = options()
+                .run_inner(&["init", "/a"])
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
+
+            match command {
+                Command::Init(dir) => assert_that!(dir).is_equal_to(PathBuf::from("/a")),
+                command => panic!("unexpected command {:?}", command),
+            }
+            Ok(())
+        }
+
+        #[test]
+        fn config_print_is_a_command() {
+            let command = options()
+                .run_inner(&["config", "print", "--config", "/a/minutes.toml"])
                 .expect("options should be parsable");
 
-            assert_that!(opts.skip_image_conversion).is_true();
+            match command {
+                Command::PrintConfig(args) => assert_that!(args.config_file)
+                    .is_equal_to(Some(PathBuf::from("/a/minutes.toml"))),
+                command => panic!("unexpected command {:?}", command),
+            }
         }
 
         #[test]
-        fn thumbnail_ratio_must_be_specified() {
-            let failure = options()
-                .run_inner(&["/a", "/b", "http://localhost/output"])
-                .expect_err("options must not be parsable");
+        fn config_print_only_takes_config_options() -> anyhow::Result<()> {
+            let command = options()
+                .run_inner(&[
+                    "config",
+                    "print",
+                    "--existing",
+                    "skip",
+                    "--publish-to",
+                    "/p",
+                ])
+                .map_err(|failure| anyhow::anyhow!("{:?}", failure))?;
 
-            assert_that!(failure.unwrap_stderr())
-                .contains("expected `--thumbnail-ratio=THUMBNAIL_RATIO`");
+            match command {
+                Command::PrintConfig(args) => {
+                    assert_that!(args.config.existing).is_equal_to(Some(ExistingFiles::Skip))
+                }
+                command => panic!("unexpected command {:?}", command),
+            }
+            for flag in ["--dry-run", "--keep-going", "--watch"] {
+                let result = options().run_inner(&["config", "print", flag][..]);
+                assert_that!(result.is_err()).is_true();
+            }
+            Ok(())
         }
 
         #[test]
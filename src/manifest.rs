@@ -0,0 +1,319 @@
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file;
+use crate::capture_time::read_capture_time;
+use crate::conversion::{
+    MinutesForConversion, OutputImageFilesForConversion, OutputLayout, SectionForConversion,
+};
+use crate::error::Error;
+use crate::image_operations::{resized_dimensions, Variant};
+use crate::output::{MinutesForOutput, SectionForOutput};
+use crate::overrides::ImageOverrides;
+use crate::OutputImageFiles;
+
+/// Everything known about the generated minutes, for tools that should not have to
+/// parse the document.
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct Manifest {
+    pub output_root: PathBuf,
+    /// Base of the URLs of the generated files.
+    #[serde(default)]
+    pub online_base_path: Option<String>,
+    #[serde(default)]
+    pub variants: Vec<Variant>,
+    pub sections: Vec<ManifestSection>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ManifestSection {
+    pub name: String,
+    /// Names of the enclosing sections and this one, outermost first.
+    pub section_path: Vec<String>,
+    pub images: Vec<ManifestImage>,
+    pub subsections: Vec<ManifestSection>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ManifestImage {
+    pub source: PathBuf,
+    pub capture_time: Option<NaiveDateTime>,
+    pub overrides: ImageOverrides,
+    /// Generated files by variant name.
+    pub variants: BTreeMap<String, ManifestVariant>,
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+#[serde(rename_all = "kebab-case")]
+pub struct ManifestVariant {
+    pub path: PathBuf,
+    pub url: String,
+    pub width: u32,
+    pub height: u32,
+    /// Size of the generated file, missing if it does not exist (yet).
+    pub bytes: Option<u64>,
+}
+
+impl Manifest {
+    /// `for_output` has to be created from `for_conversion` with `online_base_path`.
+    /// Dimensions that are not recorded in `for_conversion`, e.g. of images that are
+    /// not converted yet, are calculated from the source images.
+    pub fn create(
+        for_conversion: &MinutesForConversion,
+        for_output: &MinutesForOutput,
+        layout: &OutputLayout,
+        online_base_path: &str,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            output_root: for_conversion.output_root.clone(),
+            online_base_path: Some(online_base_path.to_string()),
+            variants: layout.variants.clone(),
+            sections: for_conversion
+                .sections
+                .iter()
+                .zip(&for_output.sections)
+                .map(|(c, o)| ManifestSection::create(c, o, &[], layout))
+                .collect::<Result<_, Error>>()?,
+        })
+    }
+
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let content = fs_err::read_to_string(path).map_err(Error::read(path))?;
+        serde_json::from_str(&content).map_err(|e| Error::parse(e).in_file(path))
+    }
+
+    /// Changes the URLs of all generated files to start with `online_base_path`.
+    pub fn rebase(&mut self, online_base_path: &str) -> Result<(), Error> {
+        for section in &mut self.sections {
+            section.rebase(&self.output_root, online_base_path)?;
+        }
+        self.online_base_path = Some(online_base_path.to_string());
+        Ok(())
+    }
+
+    /// The minutes as if they were created from the input, for rendering the document
+    /// without the source images.
+    pub fn minutes_for_output(&self) -> MinutesForOutput<'_> {
+        MinutesForOutput {
+            sections: self
+                .sections
+                .iter()
+                .map(ManifestSection::section_for_output)
+                .collect(),
+        }
+    }
+
+    /// Writes the manifest as JSON, replacing `path` only once it is complete.
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        atomic_file::replace(path, |writer| {
+            serde_json::to_writer_pretty(&mut *writer, self)
+                .map_err(|e| Error::write(path)(io::Error::from(e)))
+        })
+    }
+}
+
+impl ManifestSection {
+    fn rebase(&mut self, output_root: &Path, online_base_path: &str) -> Result<(), Error> {
+        for variant in self.images.iter_mut().flat_map(|i| i.variants.values_mut()) {
+            variant.url =
+                OutputImageFiles::create_online_path(&variant.path, output_root, online_base_path)?;
+        }
+        for subsection in &mut self.subsections {
+            subsection.rebase(output_root, online_base_path)?;
+        }
+        Ok(())
+    }
+
+    fn section_for_output(&self) -> SectionForOutput<'_> {
+        SectionForOutput {
+            name: &self.name,
+            image_files: self
+                .images
+                .iter()
+                .map(|image| OutputImageFiles {
+                    variants: image
+                        .variants
+                        .iter()
+                        .map(|(name, variant)| (name.clone(), variant.url.clone()))
+                        .collect(),
+                    dimensions: image
+                        .variants
+                        .iter()
+                        .map(|(name, variant)| (name.clone(), (variant.width, variant.height)))
+                        .collect(),
+                })
+                .collect(),
+            subsections: self
+                .subsections
+                .iter()
+                .map(ManifestSection::section_for_output)
+                .collect(),
+        }
+    }
+
+    fn create(
+        for_conversion: &SectionForConversion,
+        for_output: &SectionForOutput,
+        parent_names: &[String],
+        layout: &OutputLayout,
+    ) -> Result<Self, Error> {
+        let mut section_path = parent_names.to_vec();
+        section_path.push(for_conversion.name.to_string());
+        Ok(Self {
+            name: for_conversion.name.to_string(),
+            images: for_conversion
+                .image_files
+                .iter()
+                .zip(&for_output.image_files)
+                .map(|(c, o)| ManifestImage::create(c, o, layout))
+                .collect::<Result<_, Error>>()
+                .map_err(|e| e.in_section(for_conversion.name))?,
+            subsections: for_conversion
+                .subsections
+                .iter()
+                .zip(&for_output.subsections)
+                .map(|(c, o)| ManifestSection::create(c, o, &section_path, layout))
+                .collect::<Result<_, Error>>()?,
+            section_path,
+        })
+    }
+}
+
+impl ManifestImage {
+    fn create(
+        for_conversion: &OutputImageFilesForConversion,
+        for_output: &OutputImageFiles,
+        layout: &OutputLayout,
+    ) -> Result<Self, Error> {
+        let source = for_conversion.source_image_path;
+        let mut variants = BTreeMap::new();
+        for variant in &layout.variants {
+            let path = for_conversion.variants[&variant.name].clone();
+            let bytes = path.metadata().ok().map(|m| m.len());
+            let (width, height) = match for_conversion.dimensions.get(&variant.name) {
+                Some(dimensions) => *dimensions,
+                None => resized_dimensions(source, &variant.resize, &for_conversion.overrides)?,
+            };
+            variants.insert(
+                variant.name.clone(),
+                ManifestVariant {
+                    url: for_output.url(&variant.name)?.to_string(),
+                    path,
+                    width,
+                    height,
+                    bytes,
+                },
+            );
+        }
+        Ok(Self {
+            source: source.path().to_path_buf(),
+            capture_time: read_capture_time(source)?,
+            overrides: for_conversion.overrides,
+            variants,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use speculoos::prelude::*;
+
+    use crate::conversion::{create_minutes_for_conversion, OutputLayout};
+    use crate::image_operations::Variant;
+    use crate::images::SourceImagePath;
+    use crate::input::{Minutes, Section};
+    use crate::manifest::Manifest;
+    use crate::naming::NamingScheme;
+    use crate::output::create_minutes_for_output;
+    use crate::overrides::SectionOverrides;
+
+    #[test]
+    fn manifest_describes_every_variant() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let image = SourceImagePath::new(PathBuf::from("./src/empty-100x200.jpg"));
+        let minutes = Minutes {
+            sections: vec![Section {
+                name: "day 1".to_string(),
+                image_files: vec![],
+                overrides: SectionOverrides::default(),
+                subsections: vec![Section {
+                    name: "talk".to_string(),
+                    image_files: vec![image],
+                    overrides: SectionOverrides::default(),
+                    subsections: vec![],
+                }],
+            }],
+            ignored_files: vec![],
+        };
+        let layout = OutputLayout {
+            output_root: dir.path().to_path_buf(),
+            variants: vec!["small=50w".parse::<Variant>().map_err(anyhow::Error::msg)?],
+            naming: NamingScheme::default(),
+        };
+        let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+        let for_output = create_minutes_for_output(&for_conversion, "http://localhost")?;
+
+        let manifest = Manifest::create(&for_conversion, &for_output, &layout, "http://localhost")?;
+        let path = dir.path().join("manifest.json");
+        manifest.write(&path)?;
+
+        let talk = &manifest.sections[0].subsections[0];
+        assert_that!(talk.section_path).is_equal_to(vec!["day 1".to_string(), "talk".to_string()]);
+        let small = &talk.images[0].variants["small"];
+        assert_that!(small.url)
+            .is_equal_to("http://localhost/day%201/talk/empty-100x200_small.jpg".to_string());
+        assert_that!((small.width, small.height)).is_equal_to((50, 100));
+        assert_that!(small.bytes).is_none();
+        let json: serde_json::Value = serde_json::from_str(&fs_err::read_to_string(path)?)?;
+        assert_that!(json["sections"][0]["subsections"][0]["images"][0]["capture-time"].is_null())
+            .is_true();
+        Ok(())
+    }
+
+    #[test]
+    fn documents_can_be_rendered_from_a_rebased_manifest() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("manifest.json");
+        fs_err::write(
+            &path,
+            r#"{
+                "output-root": "out",
+                "online-base-path": "http://localhost",
+                "variants": ["small=0.3", "large=original"],
+                "sections": [{
+                    "name": "day 1",
+                    "section-path": ["day 1"],
+                    "images": [{
+                        "source": "in/day 1/a.jpg",
+                        "capture-time": "2024-05-17T09:30:05",
+                        "overrides": {"rotate": 90},
+                        "variants": {
+                            "small": {"path": "out/day 1/a_small.jpg", "url": "http://localhost/day 1/a_small.jpg", "width": 30, "height": 60, "bytes": 100},
+                            "large": {"path": "out/day 1/a_large.jpg", "url": "http://localhost/day 1/a_large.jpg", "width": 100, "height": 200, "bytes": null}
+                        }
+                    }],
+                    "subsections": []
+                }]
+            }"#,
+        )?;
+
+        let mut manifest = Manifest::read(&path)?;
+        manifest.rebase("https://example.org/minutes")?;
+        let minutes = manifest.minutes_for_output();
+
+        assert_that!(manifest.variants).has_length(2);
+        assert_that!(minutes.sections[0].name).is_equal_to("day 1");
+        assert_that!(minutes.sections[0].image_files[0].url("small")?)
+            .is_equal_to("https://example.org/minutes/day%201/a_small.jpg");
+        Ok(())
+    }
+}
@@ -1,35 +1,111 @@
+use crate::error::Error;
+use crate::html_output::{dimension_attributes, escape};
 use crate::output::{MinutesForOutput, SectionForOutput};
+use crate::OutputImageFiles;
 
-pub fn create_markdown(minutes: MinutesForOutput) -> anyhow::Result<String> {
+/// Which variants are shown inline and which are linked to.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct MarkdownOptions {
+    pub thumbnail_variant: String,
+    pub link_variant: String,
+    /// Show the thumbnails with HTML `<img>` tags that carry their dimensions and
+    /// `loading="lazy"` instead of Markdown images.
+    pub html_images: bool,
+}
+
+impl Default for MarkdownOptions {
+    fn default() -> Self {
+        Self {
+            thumbnail_variant: "small".to_string(),
+            link_variant: "large".to_string(),
+            html_images: false,
+        }
+    }
+}
+
+pub fn create_markdown(
+    minutes: MinutesForOutput,
+    options: &MarkdownOptions,
+) -> Result<String, Error> {
     let sections = minutes
         .sections
         .into_iter()
-        .map(format_section)
-        .collect::<Vec<_>>()
+        .map(|s| format_section(s, 1, options))
+        .collect::<Result<Vec<_>, Error>>()?
         .join("\n\n");
 
     Ok(sections)
 }
 
-fn format_section(s: SectionForOutput) -> String {
+/// Markdown only knows six heading levels, deeper sections share the last one.
+const MAX_HEADING_LEVEL: usize = 6;
+
+fn format_section(
+    s: SectionForOutput,
+    level: usize,
+    options: &MarkdownOptions,
+) -> Result<String, Error> {
     let images = s
         .image_files
+        .iter()
+        .map(|f| format_image(f, s.name, options))
+        .collect::<Result<Vec<_>, Error>>()?
         .into_iter()
-        .map(|f| (f.small_image, f.large_image))
-        .map(|image_paths| format!("[![{}]({})]({})", s.name, image_paths.0, image_paths.1))
-        .reduce(|acc, cur| format!("{}\n\n{}", acc, cur))
-        .unwrap_or("\n".to_string());
-    format!("# {}\n\n{}", s.name, images)
+        .reduce(|acc, cur| format!("{}\n\n{}", acc, cur));
+    let heading = format!("{} {}", "#".repeat(level.min(MAX_HEADING_LEVEL)), s.name);
+    let mut parts = match (images, s.subsections.is_empty()) {
+        (Some(images), _) => vec![heading, images],
+        (None, true) => vec![heading, "\n".to_string()],
+        (None, false) => vec![heading],
+    };
+    if s.name.is_empty() {
+        parts.remove(0);
+    }
+    for subsection in s.subsections {
+        parts.push(format_section(subsection, level + 1, options)?);
+    }
+    Ok(parts.join("\n\n"))
+}
+
+fn format_image(
+    files: &OutputImageFiles,
+    alt: &str,
+    options: &MarkdownOptions,
+) -> Result<String, Error> {
+    let thumbnail = files.url(&options.thumbnail_variant)?;
+    let link = files.url(&options.link_variant)?;
+    if !options.html_images {
+        return Ok(format!("[![{}]({})]({})", alt, thumbnail, link));
+    }
+    Ok(format!(
+        "<a href=\"{}\"><img src=\"{}\"{} alt=\"{}\" loading=\"lazy\"></a>",
+        escape(link),
+        escape(thumbnail),
+        dimension_attributes(files.dimensions(&options.thumbnail_variant)),
+        escape(alt)
+    ))
 }
 
 #[cfg(test)]
 mod tests {
     use speculoos::prelude::*;
 
-    use crate::markdown_output::create_markdown;
+    use std::collections::BTreeMap;
+
+    use crate::markdown_output::{create_markdown, MarkdownOptions};
     use crate::output::{MinutesForOutput, SectionForOutput};
     use crate::OutputImageFiles;
 
+    fn image_files(small_image: &str, large_image: &str) -> OutputImageFiles {
+        OutputImageFiles {
+            variants: BTreeMap::from([
+                ("small".to_string(), small_image.to_string()),
+                ("large".to_string(), large_image.to_string()),
+            ]),
+            dimensions: BTreeMap::new(),
+        }
+    }
+
     #[test]
     fn creates_heading_for_each_section() {
         let minutes = MinutesForOutput {
@@ -37,15 +113,17 @@ mod tests {
                 SectionForOutput {
                     name: "section 1",
                     image_files: vec![],
+                    subsections: vec![],
                 },
                 SectionForOutput {
                     name: "section 2",
                     image_files: vec![],
+                    subsections: vec![],
                 },
             ],
         };
 
-        let markdown = assert_that!(create_markdown(minutes))
+        let markdown = assert_that!(create_markdown(minutes, &MarkdownOptions::default()))
             .is_ok()
             .subject
             .to_string();
@@ -70,33 +148,23 @@ mod tests {
                 SectionForOutput {
                     name: "section 1",
                     image_files: vec![
-                        OutputImageFiles {
-                            small_image: image_path_1_small.clone(),
-                            large_image: image_path_1_large.clone(),
-                        },
-                        OutputImageFiles {
-                            small_image: image_path_2_small.clone(),
-                            large_image: image_path_2_large.clone(),
-                        },
+                        image_files(&image_path_1_small, &image_path_1_large),
+                        image_files(&image_path_2_small, &image_path_2_large),
                     ],
+                    subsections: vec![],
                 },
                 SectionForOutput {
                     name: "section 2",
                     image_files: vec![
-                        OutputImageFiles {
-                            small_image: image_path_3_small.clone(),
-                            large_image: image_path_3_large.clone(),
-                        },
-                        OutputImageFiles {
-                            small_image: image_path_4_small.clone(),
-                            large_image: image_path_4_large.clone(),
-                        },
+                        image_files(&image_path_3_small, &image_path_3_large),
+                        image_files(&image_path_4_small, &image_path_4_large),
                     ],
+                    subsections: vec![],
                 },
             ],
         };
 
-        let markdown = assert_that!(create_markdown(minutes))
+        let markdown = assert_that!(create_markdown(minutes, &MarkdownOptions::default()))
             .is_ok()
             .subject
             .to_string();
@@ -117,4 +185,136 @@ mod tests {
             image_path_4_large
         ));
     }
+
+    #[test]
+    fn uses_the_selected_variants() {
+        let minutes = MinutesForOutput {
+            sections: vec![SectionForOutput {
+                name: "section 1",
+                image_files: vec![OutputImageFiles {
+                    variants: BTreeMap::from([
+                        ("thumb".to_string(), "/a/1_thumb.jpg".to_string()),
+                        ("medium".to_string(), "/a/1_medium.jpg".to_string()),
+                        ("large".to_string(), "/a/1_large.jpg".to_string()),
+                    ]),
+                    dimensions: BTreeMap::new(),
+                }],
+                subsections: vec![],
+            }],
+        };
+        let options = MarkdownOptions {
+            thumbnail_variant: "medium".to_string(),
+            link_variant: "large".to_string(),
+            html_images: false,
+        };
+
+        let markdown = assert_that!(create_markdown(minutes, &options))
+            .is_ok()
+            .subject
+            .to_string();
+
+        assert_that!(markdown).is_equal_to(
+            "# section 1\n\n[![section 1](/a/1_medium.jpg)](/a/1_large.jpg)".to_string(),
+        );
+    }
+
+    #[test]
+    fn fails_for_unknown_variant() {
+        let minutes = MinutesForOutput {
+            sections: vec![SectionForOutput {
+                name: "section 1",
+                image_files: vec![image_files("/a/1_small.jpg", "/a/1_large.jpg")],
+                subsections: vec![],
+            }],
+        };
+        let options = MarkdownOptions {
+            thumbnail_variant: "thumb".to_string(),
+            link_variant: "large".to_string(),
+            html_images: false,
+        };
+
+        let err = assert_that!(create_markdown(minutes, &options))
+            .is_err()
+            .subject
+            .to_string();
+
+        assert_that!(err).contains("no image variant named thumb");
+    }
+
+    #[test]
+    fn html_images_carry_dimensions_and_load_lazily() {
+        let mut files = image_files("/a/1_small.jpg", "/a/1_large.jpg");
+        files.dimensions = BTreeMap::from([("small".to_string(), (300, 200))]);
+        let minutes = MinutesForOutput {
+            sections: vec![SectionForOutput {
+                name: "Q&A",
+                image_files: vec![files],
+                subsections: vec![],
+            }],
+        };
+        let options = MarkdownOptions {
+            html_images: true,
+            ..MarkdownOptions::default()
+        };
+
+        let markdown = assert_that!(create_markdown(minutes, &options))
+            .is_ok()
+            .subject
+            .to_string();
+
+        assert_that!(markdown).is_equal_to(
+            "# Q&A\n\n<a href=\"/a/1_large.jpg\"><img src=\"/a/1_small.jpg\" width=\"300\" height=\"200\" alt=\"Q&amp;A\" loading=\"lazy\"></a>"
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn heading_level_follows_section_depth() {
+        let minutes = MinutesForOutput {
+            sections: vec![SectionForOutput {
+                name: "track",
+                image_files: vec![],
+                subsections: vec![SectionForOutput {
+                    name: "session",
+                    image_files: vec![image_files("/a/1_small.jpg", "/a/1_large.jpg")],
+                    subsections: vec![],
+                }],
+            }],
+        };
+
+        let markdown = assert_that!(create_markdown(minutes, &MarkdownOptions::default()))
+            .is_ok()
+            .subject
+            .to_string();
+
+        assert_that!(markdown).is_equal_to(
+            "# track\n\n## session\n\n[![session](/a/1_small.jpg)](/a/1_large.jpg)".to_string(),
+        );
+    }
+
+    #[test]
+    fn untitled_section_has_no_heading() {
+        let minutes = MinutesForOutput {
+            sections: vec![
+                SectionForOutput {
+                    name: "",
+                    image_files: vec![image_files("/a/group_small.jpg", "/a/group_large.jpg")],
+                    subsections: vec![],
+                },
+                SectionForOutput {
+                    name: "section 1",
+                    image_files: vec![],
+                    subsections: vec![],
+                },
+            ],
+        };
+
+        let markdown = assert_that!(create_markdown(minutes, &MarkdownOptions::default()))
+            .is_ok()
+            .subject
+            .to_string();
+
+        assert_that!(markdown)
+            .starts_with("[![](/a/group_small.jpg)](/a/group_large.jpg)\n\n# section 1");
+    }
 }
@@ -0,0 +1,458 @@
+use std::collections::BTreeMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::image_operations::Variant;
+use crate::images::SourceImagePath;
+
+pub const DEFAULT_TEMPLATE: &str = "{section_path}/{stem}_{variant}.{ext}";
+
+const HASH_LENGTH: usize = 8;
+
+/// Template for the paths of generated images relative to the output root,
+/// e.g. `{section_slug}/{index:03}_{variant}.{ext_lower}` or `{hash8}_{variant}.{ext}`.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone)]
+#[serde(try_from = "String", into = "String")]
+pub struct NamingScheme {
+    template: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+enum Segment {
+    Literal(String),
+    Placeholder {
+        placeholder: Placeholder,
+        width: usize,
+    },
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Copy)]
+enum Placeholder {
+    Section,
+    SectionSlug,
+    SectionPath,
+    SectionPathSlug,
+    Stem,
+    StemSlug,
+    Index,
+    Variant,
+    Ext,
+    ExtLower,
+    Hash8,
+}
+
+impl FromStr for Placeholder {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "section" => Ok(Placeholder::Section),
+            "section_slug" => Ok(Placeholder::SectionSlug),
+            "section_path" => Ok(Placeholder::SectionPath),
+            "section_path_slug" => Ok(Placeholder::SectionPathSlug),
+            "stem" => Ok(Placeholder::Stem),
+            "stem_slug" => Ok(Placeholder::StemSlug),
+            "index" => Ok(Placeholder::Index),
+            "variant" => Ok(Placeholder::Variant),
+            "ext" => Ok(Placeholder::Ext),
+            "ext_lower" => Ok(Placeholder::ExtLower),
+            "hash8" => Ok(Placeholder::Hash8),
+            other => Err(format!(
+                "unknown placeholder {{{}}}, expected one of section, section_slug, section_path, section_path_slug, stem, stem_slug, index, variant, ext, ext_lower, hash8",
+                other
+            )),
+        }
+    }
+}
+
+impl Default for NamingScheme {
+    fn default() -> Self {
+        DEFAULT_TEMPLATE
+            .parse()
+            .expect("default template should be valid")
+    }
+}
+
+impl FromStr for NamingScheme {
+    type Err = String;
+
+    fn from_str(template: &str) -> Result<Self, Self::Err> {
+        let mut segments = vec![];
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            if start > 0 {
+                segments.push(Segment::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("unclosed placeholder in {}", template))?
+                + start;
+            let (name, width) = match rest[start + 1..end].split_once(':') {
+                Some((name, width)) => (
+                    name,
+                    width
+                        .parse()
+                        .map_err(|_| format!("invalid width {} in {}", width, template))?,
+                ),
+                None => (&rest[start + 1..end], 0),
+            };
+            segments.push(Segment::Placeholder {
+                placeholder: name.parse()?,
+                width,
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Literal(rest.to_string()));
+        }
+        if !segments.iter().any(|s| {
+            matches!(
+                s,
+                Segment::Placeholder {
+                    placeholder: Placeholder::Variant,
+                    ..
+                }
+            )
+        }) {
+            return Err(format!(
+                "template {} must contain {{variant}} so that variants do not overwrite each other",
+                template
+            ));
+        }
+        Ok(Self {
+            template: template.to_string(),
+            segments,
+        })
+    }
+}
+
+impl TryFrom<String> for NamingScheme {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for NamingScheme {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.template)
+    }
+}
+
+impl From<NamingScheme> for String {
+    fn from(value: NamingScheme) -> Self {
+        value.template
+    }
+}
+
+/// What is known about an image when its output file names are created.
+pub struct ImageNamingContext<'a> {
+    /// Names of the sections from the top level down to the section of the image.
+    pub section_path: &'a [&'a str],
+    /// Position of the image within its section, starting at 1.
+    pub index: usize,
+    pub source: &'a SourceImagePath,
+}
+
+impl NamingScheme {
+    /// Creates the output path of each variant relative to the output root.
+    pub fn output_paths(
+        &self,
+        context: &ImageNamingContext,
+        variants: &[Variant],
+    ) -> Result<BTreeMap<String, PathBuf>, Error> {
+        let section = context.section_path.last().copied().unwrap_or_default();
+        let stem = context.source.file_stem()?;
+        let extension = context.source.extension()?;
+        let hash = if self.uses(Placeholder::Hash8) {
+            context.source.content_hash()?[..HASH_LENGTH].to_string()
+        } else {
+            String::new()
+        };
+        variants
+            .iter()
+            .map(|variant| {
+                let rendered = self
+                    .segments
+                    .iter()
+                    .map(|segment| match segment {
+                        Segment::Literal(literal) => literal.clone(),
+                        Segment::Placeholder { placeholder, width } => {
+                            let value = match placeholder {
+                                Placeholder::Section => sanitize(section),
+                                Placeholder::SectionSlug => slugify(section),
+                                Placeholder::SectionPath => context
+                                    .section_path
+                                    .iter()
+                                    .map(|s| sanitize(s))
+                                    .collect::<Vec<_>>()
+                                    .join("/"),
+                                Placeholder::SectionPathSlug => context
+                                    .section_path
+                                    .iter()
+                                    .map(|s| slugify(s))
+                                    .collect::<Vec<_>>()
+                                    .join("/"),
+                                Placeholder::Stem => sanitize(&stem),
+                                Placeholder::StemSlug => slugify(&stem),
+                                Placeholder::Index => context.index.to_string(),
+                                Placeholder::Variant => variant.name.clone(),
+                                Placeholder::Ext => extension.clone(),
+                                Placeholder::ExtLower => extension.to_lowercase(),
+                                Placeholder::Hash8 => hash.clone(),
+                            };
+                            format!("{:0>width$}", value, width = width)
+                        }
+                    })
+                    .collect::<String>();
+                Ok((variant.name.clone(), to_relative_path(&rendered)?))
+            })
+            .collect()
+    }
+
+    fn uses(&self, placeholder: Placeholder) -> bool {
+        self.segments
+            .iter()
+            .any(|s| matches!(s, Segment::Placeholder { placeholder: p, .. } if *p == placeholder))
+    }
+}
+
+/// Empty components are dropped, so that sections without name (e.g. an untitled
+/// introduction) do not create a directory.
+fn to_relative_path(rendered: &str) -> Result<PathBuf, Error> {
+    rendered
+        .split('/')
+        .filter(|component| !component.is_empty())
+        .map(|component| match component {
+            "." | ".." => None,
+            component => Some(component),
+        })
+        .collect::<Option<PathBuf>>()
+        .filter(|path| path.file_name().is_some())
+        .ok_or_else(|| Error::InvalidFileName(rendered.to_string()))
+}
+
+/// Replaces characters that are not allowed in file names on common platforms, so
+/// that section titles like `09:00 – 10:30` can be used as directory names.
+fn sanitize(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '/' | '\\' | '<' | '>' | ':' | '"' | '|' | '?' | '*' => '-',
+            c if c.is_control() => '-',
+            c => c,
+        })
+        .collect()
+}
+
+/// Lowercase ASCII version of `text` in which everything but letters and digits is
+/// replaced by `-`.
+pub fn slugify(text: &str) -> String {
+    let mut slug = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        let replacement = match c {
+            'ä' => "ae",
+            'ö' => "oe",
+            'ü' => "ue",
+            'ß' => "ss",
+            'à' | 'á' | 'â' | 'ã' | 'å' => "a",
+            'è' | 'é' | 'ê' | 'ë' => "e",
+            'ì' | 'í' | 'î' | 'ï' => "i",
+            'ò' | 'ó' | 'ô' | 'õ' | 'ø' => "o",
+            'ù' | 'ú' | 'û' => "u",
+            'ç' => "c",
+            'ñ' => "n",
+            c if c.is_ascii_alphanumeric() => {
+                slug.push(c);
+                continue;
+            }
+            _ => "-",
+        };
+        if replacement == "-" && (slug.is_empty() || slug.ends_with('-')) {
+            continue;
+        }
+        slug.push_str(replacement);
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        "untitled".to_string()
+    } else {
+        slug.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
+    use speculoos::prelude::*;
+
+    use crate::image_operations::Variant;
+    use crate::images::SourceImagePath;
+    use crate::naming::{slugify, ImageNamingContext, NamingScheme};
+
+    fn variants() -> Vec<Variant> {
+        vec![
+            "small=0.3".parse().unwrap(),
+            "large=original".parse().unwrap(),
+        ]
+    }
+
+    #[test]
+    fn default_scheme_uses_section_stem_and_variant() -> anyhow::Result<()> {
+        let source = SourceImagePath::new(PathBuf::from("/input/section-1/1.jpg"));
+        let context = ImageNamingContext {
+            section_path: &["section-1"],
+            index: 1,
+            source: &source,
+        };
+
+        let paths = NamingScheme::default().output_paths(&context, &variants())?;
+
+        assert_that!(paths).is_equal_to(BTreeMap::from([
+            (
+                "large".to_string(),
+                PathBuf::from("section-1").join("1_large.jpg"),
+            ),
+            (
+                "small".to_string(),
+                PathBuf::from("section-1").join("1_small.jpg"),
+            ),
+        ]));
+        Ok(())
+    }
+
+    #[test]
+    fn slugs_and_padded_index_are_supported() -> anyhow::Result<()> {
+        let source = SourceImagePath::new(PathBuf::from("/input/Über Uns/IMG 1.JPG"));
+        let context = ImageNamingContext {
+            section_path: &["Über Uns"],
+            index: 7,
+            source: &source,
+        };
+        let scheme: NamingScheme = "{section_slug}/{index:03}_{stem_slug}_{variant}.{ext_lower}"
+            .parse()
+            .unwrap();
+
+        let paths = scheme.output_paths(&context, &variants())?;
+
+        assert_that!(paths["small"])
+            .is_equal_to(PathBuf::from("ueber-uns").join("007_img-1_small.jpg"));
+        Ok(())
+    }
+
+    #[test]
+    fn hash_placeholder_uses_file_content() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("1.jpg");
+        fs_err::write(&path, "abc")?;
+        let source = SourceImagePath::new(path);
+        let context = ImageNamingContext {
+            section_path: &["section-1"],
+            index: 1,
+            source: &source,
+        };
+        let scheme: NamingScheme = "{hash8}_{variant}.{ext}".parse().unwrap();
+
+        let paths = scheme.output_paths(&context, &variants())?;
+
+        assert_that!(paths["large"]).is_equal_to(PathBuf::from("ba7816bf_large.jpg"));
+        Ok(())
+    }
+
+    #[test]
+    fn section_path_mirrors_nested_sections() -> anyhow::Result<()> {
+        let source = SourceImagePath::new(PathBuf::from("/input/Track A/Session 1/1.jpg"));
+        let context = ImageNamingContext {
+            section_path: &["Track A", "Session 1"],
+            index: 1,
+            source: &source,
+        };
+        let scheme: NamingScheme = "{section_path_slug}/{stem}_{variant}.{ext}"
+            .parse()
+            .unwrap();
+
+        let default_paths = NamingScheme::default().output_paths(&context, &variants())?;
+        let slug_paths = scheme.output_paths(&context, &variants())?;
+
+        assert_that!(default_paths["small"]).is_equal_to(
+            PathBuf::from("Track A")
+                .join("Session 1")
+                .join("1_small.jpg"),
+        );
+        assert_that!(slug_paths["small"]).is_equal_to(
+            PathBuf::from("track-a")
+                .join("session-1")
+                .join("1_small.jpg"),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn untitled_section_does_not_create_a_directory() -> anyhow::Result<()> {
+        let source = SourceImagePath::new(PathBuf::from("/input/group.jpg"));
+        let context = ImageNamingContext {
+            section_path: &[""],
+            index: 1,
+            source: &source,
+        };
+
+        let paths = NamingScheme::default().output_paths(&context, &variants())?;
+
+        assert_that!(paths["small"]).is_equal_to(PathBuf::from("group_small.jpg"));
+        Ok(())
+    }
+
+    #[test]
+    fn section_names_are_made_safe_for_file_systems() -> anyhow::Result<()> {
+        let source = SourceImagePath::new(PathBuf::from("/input/1.jpg"));
+        let context = ImageNamingContext {
+            section_path: &["09:00 – 10:30", "Q/A"],
+            index: 1,
+            source: &source,
+        };
+
+        let paths = NamingScheme::default().output_paths(&context, &variants())?;
+
+        assert_that!(paths["small"]).is_equal_to(
+            PathBuf::from("09-00 – 10-30")
+                .join("Q-A")
+                .join("1_small.jpg"),
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_templates_are_rejected() {
+        assert_that!("{section}/{stem}.{ext}".parse::<NamingScheme>()).is_err();
+        assert_that!("{section}/{unknown}_{variant}".parse::<NamingScheme>()).is_err();
+        assert_that!("{section/{variant}".parse::<NamingScheme>()).is_err();
+        assert_that!("{index:x}_{variant}".parse::<NamingScheme>()).is_err();
+    }
+
+    #[test]
+    fn generated_paths_must_stay_below_the_output_root() {
+        let source = SourceImagePath::new(PathBuf::from("/input/section-1/1.jpg"));
+        let context = ImageNamingContext {
+            section_path: &["section-1"],
+            index: 1,
+            source: &source,
+        };
+        let scheme: NamingScheme = "../{variant}.{ext}".parse().unwrap();
+
+        assert_that!(scheme.output_paths(&context, &variants())).is_err();
+    }
+
+    #[test]
+    fn slugify_creates_lowercase_ascii() {
+        assert_that!(slugify("Kaffee & Kuchen: Größe!"))
+            .is_equal_to("kaffee-kuchen-groesse".to_string());
+        assert_that!(slugify("  ")).is_equal_to("untitled".to_string());
+    }
+}
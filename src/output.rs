@@ -1,41 +1,77 @@
 use std::path::Path;
 
-use anyhow::Context;
+use percent_encoding::{utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
 
-use crate::conversion::OutputImageFilesForConversion;
+use crate::conversion::{
+    MinutesForConversion, OutputImageFilesForConversion, SectionForConversion,
+};
+use crate::error::Error;
 use crate::OutputImageFiles;
 
+/// Everything but the unreserved characters and `/` is percent-encoded in URL paths.
+pub(crate) const PATH_ENCODING: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
 impl OutputImageFiles {
     pub fn create(
         source: &OutputImageFilesForConversion,
+        output_root: &Path,
         online_base_path: impl AsRef<str>,
-    ) -> anyhow::Result<Self> {
-        anyhow::Ok(Self {
-            small_image: Self::create_online_path(&source.small_image, online_base_path.as_ref())?,
-            large_image: Self::create_online_path(&source.large_image, online_base_path.as_ref())?,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            variants: source
+                .variants
+                .iter()
+                .map(|(name, path)| {
+                    Ok((
+                        name.clone(),
+                        Self::create_online_path(path, output_root, online_base_path.as_ref())?,
+                    ))
+                })
+                .collect::<Result<_, Error>>()?,
+            dimensions: source.dimensions.clone(),
         })
     }
 
-    fn create_online_path(
+    pub fn dimensions(&self, variant_name: &str) -> Option<(u32, u32)> {
+        self.dimensions.get(variant_name).copied()
+    }
+
+    pub fn url(&self, variant_name: &str) -> Result<&str, Error> {
+        self.variants
+            .get(variant_name)
+            .map(String::as_str)
+            .ok_or_else(|| Error::UnknownVariant(variant_name.to_string()))
+    }
+
+    /// The path of the image below `online_base_path`, percent-encoded so that e.g.
+    /// spaces in section names do not end Markdown links.
+    pub(crate) fn create_online_path(
         image_path: impl AsRef<Path>,
+        output_root: &Path,
         online_base_path: &str,
-    ) -> anyhow::Result<String> {
+    ) -> Result<String, Error> {
         let image_path = image_path.as_ref();
-        let base_path = image_path
-            .parent()
-            .and_then(Path::parent)
-            .with_context(|| {
-                format!(
-                    "Could not derive base path of image {}",
-                    image_path.to_string_lossy()
-                )
-            })?;
-
-        let image_online_path = image_path.strip_prefix(base_path)?;
-        anyhow::Ok(format!(
+        let image_online_path =
+            image_path
+                .strip_prefix(output_root)
+                .map_err(|_| Error::OutsideOutput {
+                    path: image_path.to_path_buf(),
+                    output_root: output_root.to_path_buf(),
+                })?;
+
+        Ok(format!(
             "{}/{}",
             online_base_path,
-            image_online_path.to_string_lossy()
+            image_online_path
+                .iter()
+                .map(|c| utf8_percent_encode(&c.to_string_lossy(), PATH_ENCODING).to_string())
+                .collect::<Vec<_>>()
+                .join("/")
         ))
     }
 }
@@ -44,9 +80,65 @@ impl OutputImageFiles {
 pub struct SectionForOutput<'source> {
     pub name: &'source str,
     pub image_files: Vec<OutputImageFiles>,
+    pub subsections: Vec<SectionForOutput<'source>>,
+}
+
+impl<'source> SectionForOutput<'source> {
+    fn collect_image_files<'a>(&'a self, image_files: &mut Vec<&'a OutputImageFiles>) {
+        image_files.extend(self.image_files.iter());
+        self.subsections
+            .iter()
+            .for_each(|s| s.collect_image_files(image_files));
+    }
+
+    pub fn create(
+        section: &SectionForConversion<'source>,
+        output_root: &Path,
+        online_base_path: &str,
+    ) -> Result<Self, Error> {
+        Ok(Self {
+            name: section.name,
+            image_files: section
+                .image_files
+                .iter()
+                .map(|f| OutputImageFiles::create(f, output_root, online_base_path))
+                .collect::<Result<_, Error>>()?,
+            subsections: section
+                .subsections
+                .iter()
+                .map(|s| Self::create(s, output_root, online_base_path))
+                .collect::<Result<_, Error>>()?,
+        })
+    }
 }
 
 #[derive(Debug)]
 pub struct MinutesForOutput<'source> {
     pub sections: Vec<SectionForOutput<'source>>,
 }
+
+impl MinutesForOutput<'_> {
+    /// All images of all sections including nested ones, in document order.
+    pub fn image_files(&self) -> Vec<&OutputImageFiles> {
+        let mut image_files = vec![];
+        self.sections
+            .iter()
+            .for_each(|s| s.collect_image_files(&mut image_files));
+        image_files
+    }
+}
+
+pub fn create_minutes_for_output<'source>(
+    for_conversion: &MinutesForConversion<'source>,
+    online_base_path: impl AsRef<str>,
+) -> Result<MinutesForOutput<'source>, Error> {
+    Ok(MinutesForOutput {
+        sections: for_conversion
+            .sections
+            .iter()
+            .map(|s| {
+                SectionForOutput::create(s, &for_conversion.output_root, online_base_path.as_ref())
+            })
+            .collect::<Result<_, Error>>()?,
+    })
+}
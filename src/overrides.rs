@@ -0,0 +1,251 @@
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::Error;
+use crate::images::SourceImagePath;
+use crate::source::{FileSystemSource, MinutesSource};
+
+pub const OVERRIDES_FILE_NAME: &str = "overrides.toml";
+
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct SectionOverrides {
+    images: BTreeMap<String, ImageOverrides>,
+    /// Keys that match no file of the section, see [`Self::match_files`].
+    unmatched: Vec<String>,
+}
+
+impl SectionOverrides {
+    pub fn read_from_dir(section_dir: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::read_from_source(
+            &FileSystemSource::new(section_dir.as_ref().to_path_buf()),
+            Path::new(""),
+        )
+    }
+
+    pub fn read_from_source(source: &dyn MinutesSource, section_dir: &Path) -> Result<Self, Error> {
+        let overrides_path = section_dir.join(OVERRIDES_FILE_NAME);
+        if !source.contains_file(&overrides_path)? {
+            return Ok(Self::default());
+        }
+        let path = source.root().join(&overrides_path);
+        let mut content = String::new();
+        source
+            .open(&overrides_path)?
+            .read_to_string(&mut content)
+            .map_err(Error::read(&path))?;
+        Self::parse(&content).map_err(|e| e.in_file(&path))
+    }
+
+    pub fn parse(content: &str) -> Result<Self, Error> {
+        Ok(Self {
+            images: toml::from_str(content).map_err(Error::parse)?,
+            unmatched: vec![],
+        })
+    }
+
+    /// Remembers the keys that match none of `files`, e.g. because of a typo.
+    pub fn match_files(&mut self, files: &[SourceImagePath]) {
+        self.unmatched = self
+            .images
+            .keys()
+            .filter(|key| {
+                !files
+                    .iter()
+                    .any(|f| f.path().file_name() == Some(key.as_ref()))
+            })
+            .cloned()
+            .collect();
+    }
+
+    pub fn unmatched_keys(&self) -> &[String] {
+        &self.unmatched
+    }
+
+    pub fn for_image(&self, image: &SourceImagePath) -> ImageOverrides {
+        image
+            .path()
+            .file_name()
+            .and_then(|n| self.images.get(n.to_string_lossy().as_ref()))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Copy, Default)]
+#[serde(deny_unknown_fields)]
+pub struct ImageOverrides {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rotate: Option<Rotation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub crop: Option<Crop>,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub skip: bool,
+}
+
+impl ImageOverrides {
+    pub fn changes_pixels(&self) -> bool {
+        self.rotate.is_some() || self.crop.is_some()
+    }
+}
+
+/// Clockwise rotation of the stored pixels that replaces the EXIF orientation.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Copy)]
+#[serde(try_from = "u16", into = "u16")]
+pub enum Rotation {
+    None,
+    Clockwise90,
+    Clockwise180,
+    Clockwise270,
+}
+
+impl Rotation {
+    pub fn as_exif_orientation(self) -> u8 {
+        match self {
+            Rotation::None => 1,
+            Rotation::Clockwise90 => 6,
+            Rotation::Clockwise180 => 3,
+            Rotation::Clockwise270 => 8,
+        }
+    }
+}
+
+impl From<Rotation> for u16 {
+    fn from(value: Rotation) -> Self {
+        match value {
+            Rotation::None => 0,
+            Rotation::Clockwise90 => 90,
+            Rotation::Clockwise180 => 180,
+            Rotation::Clockwise270 => 270,
+        }
+    }
+}
+
+impl TryFrom<u16> for Rotation {
+    type Error = String;
+
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(Rotation::None),
+            90 => Ok(Rotation::Clockwise90),
+            180 => Ok(Rotation::Clockwise180),
+            270 => Ok(Rotation::Clockwise270),
+            other => Err(format!(
+                "rotation must be one of 0, 90, 180 or 270 but was {}",
+                other
+            )),
+        }
+    }
+}
+
+/// Crop rectangle in pixels of the correctly oriented, full-size image.
+#[derive(Serialize, Deserialize, Eq, PartialEq, Debug, Clone, Copy)]
+#[serde(from = "[u32; 4]", into = "[u32; 4]")]
+pub struct Crop {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Crop {
+    /// Whether the rectangle is not empty and inside an image of the given size.
+    pub fn fits_within(&self, width: u32, height: u32) -> bool {
+        self.width > 0
+            && self.height > 0
+            && u64::from(self.x) + u64::from(self.width) <= u64::from(width)
+            && u64::from(self.y) + u64::from(self.height) <= u64::from(height)
+    }
+}
+
+impl From<Crop> for [u32; 4] {
+    fn from(crop: Crop) -> Self {
+        [crop.x, crop.y, crop.width, crop.height]
+    }
+}
+
+impl From<[u32; 4]> for Crop {
+    fn from([x, y, width, height]: [u32; 4]) -> Self {
+        Self {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use speculoos::prelude::*;
+
+    use crate::images::SourceImagePath;
+    use crate::overrides::{Crop, ImageOverrides, Rotation, SectionOverrides};
+
+    #[test]
+    fn parses_overrides_per_file_name() -> anyhow::Result<()> {
+        let overrides = SectionOverrides::parse(
+            r#"
+            ["1.jpg"]
+            rotate = 90
+            crop = [1, 2, 30, 40]
+
+            ["2.jpg"]
+            skip = true
+            "#,
+        )?;
+
+        let image_1 = SourceImagePath::new(PathBuf::from("/input/section/1.jpg"));
+        let image_2 = SourceImagePath::new(PathBuf::from("/input/section/2.jpg"));
+        assert_that!(overrides.for_image(&image_1)).is_equal_to(ImageOverrides {
+            rotate: Some(Rotation::Clockwise90),
+            crop: Some(Crop {
+                x: 1,
+                y: 2,
+                width: 30,
+                height: 40,
+            }),
+            skip: false,
+        });
+        assert_that!(overrides.for_image(&image_2).skip).is_true();
+        Ok(())
+    }
+
+    #[test]
+    fn images_without_entry_have_no_overrides() -> anyhow::Result<()> {
+        let overrides = SectionOverrides::parse("[\"1.jpg\"]\nskip = true\n")?;
+
+        let image = SourceImagePath::new(PathBuf::from("/input/section/3.jpg"));
+        assert_that!(overrides.for_image(&image)).is_equal_to(ImageOverrides::default());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_rotation_that_is_not_a_multiple_of_90() {
+        let result = SectionOverrides::parse("[\"1.jpg\"]\nrotate = 45\n");
+
+        let err = assert_that!(result).is_err().subject;
+        assert_that!(err.to_string()).contains("rotation must be one of");
+    }
+
+    #[test]
+    fn rejects_unknown_keys() {
+        let result = SectionOverrides::parse("[\"1.jpg\"]\nrotation = 90\n");
+
+        assert_that!(result).is_err();
+    }
+
+    #[test]
+    fn missing_overrides_file_yields_empty_overrides() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let overrides = SectionOverrides::read_from_dir(dir.path())?;
+
+        assert_that!(overrides).is_equal_to(SectionOverrides::default());
+        Ok(())
+    }
+}
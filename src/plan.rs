@@ -0,0 +1,566 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{Display, Formatter};
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::atomic_file;
+use crate::conversion::{MinutesForConversion, OutputLayout};
+use crate::error::Error;
+use crate::image_operations::{
+    generated_dimensions, resized_dimensions, save_as_resized_image, SavedImage, StepDurations,
+    Variant,
+};
+use crate::images::SourceImagePath;
+use crate::output::MinutesForOutput;
+use crate::overrides::ImageOverrides;
+
+/// What to do with generated files that exist from an earlier run.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Eq, PartialEq, Default)]
+#[serde(try_from = "String", into = "String")]
+pub enum ExistingFiles {
+    #[default]
+    Fail,
+    Overwrite,
+    Skip,
+}
+
+impl FromStr for ExistingFiles {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fail" => Ok(ExistingFiles::Fail),
+            "overwrite" => Ok(ExistingFiles::Overwrite),
+            "skip" => Ok(ExistingFiles::Skip),
+            other => Err(format!(
+                "unknown policy {}, expected fail, overwrite or skip",
+                other
+            )),
+        }
+    }
+}
+
+impl TryFrom<String> for ExistingFiles {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for ExistingFiles {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            ExistingFiles::Fail => "fail",
+            ExistingFiles::Overwrite => "overwrite",
+            ExistingFiles::Skip => "skip",
+        })
+    }
+}
+
+impl From<ExistingFiles> for String {
+    fn from(value: ExistingFiles) -> Self {
+        value.to_string()
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Action {
+    Create,
+    Overwrite,
+    Skip,
+    /// The file exists and the policy is [`ExistingFiles::Fail`].
+    Conflict,
+}
+
+impl Display for Action {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.pad(match self {
+            Action::Create => "create",
+            Action::Overwrite => "overwrite",
+            Action::Skip => "skip",
+            Action::Conflict => "exists",
+        })
+    }
+}
+
+/// One generated file: a variant of a source image.
+#[derive(Debug, Clone)]
+pub struct PlannedFile {
+    pub source_image_path: SourceImagePath,
+    pub overrides: ImageOverrides,
+    pub variant: Variant,
+    pub output_path: PathBuf,
+    pub action: Action,
+    /// Names of the section of the image and the enclosing ones, outermost first.
+    pub section_path: Vec<String>,
+}
+
+/// An image of which at least one variant could not be converted.
+#[derive(Debug)]
+pub struct FailedImage {
+    pub section_path: Vec<String>,
+    pub source_image_path: PathBuf,
+    pub error: Error,
+}
+
+impl Display for FailedImage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: ", self.section_path.join(" / "))?;
+        let cause = self.error.cause();
+        if cause.path() != Some(self.source_image_path.as_path()) {
+            write!(f, "{}: ", self.source_image_path.to_string_lossy())?;
+        }
+        write!(f, "{}", cause.full_message())
+    }
+}
+
+impl PlannedFile {
+    /// Returns `None` for skipped files.
+    fn execute(&self) -> Result<Option<SavedImage>, Error> {
+        if matches!(self.action, Action::Skip | Action::Conflict) {
+            return Ok(None);
+        }
+        // An overwritten file is only replaced once the new one is complete.
+        save_as_resized_image(
+            &self.source_image_path,
+            &self.output_path,
+            &self.variant.resize,
+            &self.overrides,
+            self.action == Action::Overwrite,
+        )
+        .map(Some)
+    }
+}
+
+struct ConvertedImage {
+    stats: ConversionStats,
+    /// Of all files of the image, including skipped ones.
+    dimensions: Vec<(PathBuf, (u32, u32))>,
+}
+
+/// Converts the files of one image that are not skipped and reads the dimensions of
+/// the skipped ones. If one of them fails, the files created so far are removed again
+/// so that they do not get in the way of the next run.
+fn convert_image(files: &[PlannedFile]) -> Result<ConvertedImage, Error> {
+    let mut created = vec![];
+    let result = convert_image_files(files, &mut created);
+    if result.is_err() {
+        for path in created {
+            let _ = fs_err::remove_file(path);
+        }
+    }
+    result
+}
+
+fn convert_image_files<'a>(
+    files: &'a [PlannedFile],
+    created: &mut Vec<&'a Path>,
+) -> Result<ConvertedImage, Error> {
+    let mut stats = ConversionStats::default();
+    let mut dimensions = vec![];
+    for file in files {
+        let saved = file.execute()?;
+        if saved.is_some() && file.action == Action::Create {
+            created.push(&file.output_path);
+        }
+        let file_dimensions = match saved {
+            Some(saved) => {
+                stats.durations += saved.durations;
+                stats.images = 1;
+                stats.files += 1;
+                stats.bytes_in = saved.bytes_in;
+                stats.bytes_out += saved.bytes_out;
+                (saved.width, saved.height)
+            }
+            None => generated_dimensions(&file.output_path)?,
+        };
+        dimensions.push((file.output_path.clone(), file_dimensions));
+    }
+    Ok(ConvertedImage { stats, dimensions })
+}
+
+/// How far [`ConversionPlan::execute`] got, reported after each image.
+#[derive(Debug, Clone)]
+pub struct Progress<'a> {
+    pub images_done: usize,
+    pub image_count: usize,
+    /// Section of the last image.
+    pub section_path: &'a [String],
+    pub section_images_done: usize,
+    pub section_image_count: usize,
+    pub elapsed: Duration,
+}
+
+impl Progress<'_> {
+    /// Estimated time until all images are done, assuming that the remaining ones take
+    /// as long as the done ones on average.
+    pub fn remaining(&self) -> Duration {
+        if self.images_done == 0 {
+            return Duration::ZERO;
+        }
+        self.elapsed
+            .mul_f64((self.image_count - self.images_done) as f64 / self.images_done as f64)
+    }
+}
+
+/// Totals of the images that were converted, not including skipped or failed ones.
+#[derive(Eq, PartialEq, Debug, Clone, Copy, Default)]
+pub struct ConversionStats {
+    pub images: usize,
+    pub files: usize,
+    /// Size of the source images.
+    pub bytes_in: u64,
+    /// Size of the generated files.
+    pub bytes_out: u64,
+    pub durations: StepDurations,
+    pub elapsed: Duration,
+}
+
+impl AddAssign for ConversionStats {
+    fn add_assign(&mut self, other: Self) {
+        self.images += other.images;
+        self.files += other.files;
+        self.bytes_in += other.bytes_in;
+        self.bytes_out += other.bytes_out;
+        self.durations += other.durations;
+        self.elapsed += other.elapsed;
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct ConversionReport {
+    pub failed: Vec<FailedImage>,
+    pub stats: ConversionStats,
+    /// Dimensions of the generated (or skipped) files by output path, see
+    /// [`MinutesForConversion::record_dimensions`].
+    pub dimensions: BTreeMap<PathBuf, (u32, u32)>,
+}
+
+/// The files a conversion creates, decided before anything is written.
+#[derive(Debug, Clone)]
+pub struct ConversionPlan {
+    pub files: Vec<PlannedFile>,
+}
+
+impl ConversionPlan {
+    /// Looks at the output files that exist already, but does not change anything.
+    pub fn create(
+        for_conversion: &MinutesForConversion,
+        layout: &OutputLayout,
+        existing: ExistingFiles,
+    ) -> Self {
+        let files = for_conversion
+            .image_files_by_section()
+            .into_iter()
+            .flat_map(|(section_path, f)| {
+                let section_path: Vec<String> =
+                    section_path.iter().map(|name| name.to_string()).collect();
+                layout.variants.iter().map(move |variant| {
+                    let output_path = f.variants[&variant.name].clone();
+                    let action = match (output_path.exists(), existing) {
+                        (false, _) => Action::Create,
+                        (true, ExistingFiles::Fail) => Action::Conflict,
+                        (true, ExistingFiles::Overwrite) => Action::Overwrite,
+                        (true, ExistingFiles::Skip) => Action::Skip,
+                    };
+                    PlannedFile {
+                        source_image_path: f.source_image_path.clone(),
+                        overrides: f.overrides,
+                        variant: variant.clone(),
+                        output_path,
+                        action,
+                        section_path: section_path.clone(),
+                    }
+                })
+            })
+            .collect();
+        Self { files }
+    }
+
+    pub fn count(&self, action: Action) -> usize {
+        self.files.iter().filter(|f| f.action == action).count()
+    }
+
+    /// Converts the images. Fails before writing anything if a file exists that may
+    /// not be overwritten. Temporary files left by an interrupted run are removed.
+    /// With `keep_going`, images that cannot be converted are reported instead of
+    /// stopping at the first one; their remaining variants are not converted and the
+    /// ones created already are removed.
+    /// `on_progress` is called after each image.
+    pub fn execute(
+        &self,
+        keep_going: bool,
+        mut on_progress: impl FnMut(&Progress),
+    ) -> Result<ConversionReport, Error> {
+        if let Some(conflict) = self.files.iter().find(|f| f.action == Action::Conflict) {
+            return Err(Error::DestinationExists {
+                path: conflict.output_path.clone(),
+            });
+        }
+        atomic_file::remove_leftovers(self.files.iter().map(|f| f.output_path.as_path()))?;
+        let start = Instant::now();
+        let images: Vec<&[PlannedFile]> = self
+            .files
+            .chunk_by(|a, b| a.source_image_path == b.source_image_path)
+            .collect();
+        let mut section_image_counts: HashMap<&[String], usize> = HashMap::new();
+        for files in &images {
+            *section_image_counts
+                .entry(&files[0].section_path)
+                .or_default() += 1;
+        }
+        let mut section_images_done: HashMap<&[String], usize> = HashMap::new();
+        let mut report = ConversionReport::default();
+        for (index, files) in images.iter().enumerate() {
+            let image = &files[0];
+            match convert_image(files) {
+                Ok(converted) => {
+                    report.stats += converted.stats;
+                    report.dimensions.extend(converted.dimensions);
+                }
+                Err(error) => {
+                    let error = image
+                        .section_path
+                        .iter()
+                        .rev()
+                        .fold(error, |error, name| error.in_section(name));
+                    if !keep_going {
+                        return Err(error);
+                    }
+                    report.failed.push(FailedImage {
+                        section_path: image.section_path.clone(),
+                        source_image_path: image.source_image_path.path().to_path_buf(),
+                        error,
+                    });
+                }
+            }
+            let section_done = section_images_done.entry(&image.section_path).or_default();
+            *section_done += 1;
+            on_progress(&Progress {
+                images_done: index + 1,
+                image_count: images.len(),
+                section_path: &image.section_path,
+                section_images_done: *section_done,
+                section_image_count: section_image_counts[image.section_path.as_slice()],
+                elapsed: start.elapsed(),
+            });
+        }
+        report.stats.elapsed = start.elapsed();
+        Ok(report)
+    }
+
+    /// Lists what [`Self::execute`] would do including the size of each file and,
+    /// if known, its URL.
+    pub fn describe(&self, urls: &BTreeMap<PathBuf, String>) -> Result<String, Error> {
+        let mut description = String::new();
+        for file in &self.files {
+            let (width, height) = resized_dimensions(
+                &file.source_image_path,
+                &file.variant.resize,
+                &file.overrides,
+            )?;
+            description.push_str(&format!(
+                "{:<9} {} ({}x{}) from {}",
+                file.action,
+                file.output_path.to_string_lossy(),
+                width,
+                height,
+                file.source_image_path.path().to_string_lossy()
+            ));
+            if let Some(url) = urls.get(&file.output_path) {
+                description.push_str(&format!(" at {}", url));
+            }
+            description.push('\n');
+        }
+        description.push_str(&format!(
+            "{} to create, {} to overwrite, {} to skip, {} already existing\n",
+            self.count(Action::Create),
+            self.count(Action::Overwrite),
+            self.count(Action::Skip),
+            self.count(Action::Conflict)
+        ));
+        Ok(description)
+    }
+}
+
+/// URL of each generated file by its output path.
+pub fn online_urls(
+    for_conversion: &MinutesForConversion,
+    for_output: &MinutesForOutput,
+) -> BTreeMap<PathBuf, String> {
+    for_conversion
+        .image_files()
+        .into_iter()
+        .zip(for_output.image_files())
+        .flat_map(|(converted, output)| {
+            converted.variants.iter().filter_map(|(name, path)| {
+                output
+                    .variants
+                    .get(name)
+                    .map(|url| (path.clone(), url.clone()))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use speculoos::prelude::*;
+
+    use crate::conversion::create_minutes_for_conversion;
+    use crate::conversion::test_support::layout;
+    use crate::images::SourceImagePath;
+    use crate::input::{Minutes, Section};
+    use crate::output::create_minutes_for_output;
+    use crate::overrides::SectionOverrides;
+    use crate::plan::{online_urls, Action, ConversionPlan, ExistingFiles};
+
+    fn minutes() -> Minutes {
+        Minutes {
+            sections: vec![Section {
+                name: "session".to_string(),
+                image_files: vec![SourceImagePath::new(PathBuf::from(
+                    "./src/empty-100x200.jpg",
+                ))],
+                overrides: SectionOverrides::default(),
+                subsections: vec![],
+            }],
+            ignored_files: vec![],
+        }
+    }
+
+    const VARIANTS: &[&str] = &["small=50w", "large=original"];
+
+    #[test]
+    fn plan_describes_the_files_without_writing_them() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let minutes = minutes();
+        let layout = layout(&dir.path().join("out"), VARIANTS)?;
+        let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+        let for_output = create_minutes_for_output(&for_conversion, "http://localhost")?;
+
+        let plan = ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Fail);
+        let description = plan.describe(&online_urls(&for_conversion, &for_output))?;
+
+        assert_that!(plan.count(Action::Create)).is_equal_to(2);
+        assert_that!(description).contains("(50x100)");
+        assert_that!(description).contains("at http://localhost/session/empty-100x200_small.jpg");
+        assert_that!(description).contains("2 to create, 0 to overwrite");
+        assert_that!(dir.path().join("out").exists()).is_false();
+        Ok(())
+    }
+
+    #[test]
+    fn existing_files_follow_the_policy() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let minutes = minutes();
+        let layout = layout(dir.path(), VARIANTS)?;
+        let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+        ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Fail)
+            .execute(false, |_| {})?;
+
+        let failing = ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Fail);
+        let skipping = ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Skip);
+        let overwriting =
+            ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Overwrite);
+
+        assert_that!(failing.count(Action::Conflict)).is_equal_to(2);
+        let result = failing.execute(false, |_| {});
+        let err = assert_that!(result).is_err().subject.to_string();
+        assert_that!(err).contains("exists already");
+        assert_that!(skipping.count(Action::Skip)).is_equal_to(2);
+        skipping.execute(false, |_| {})?;
+        assert_that!(overwriting.count(Action::Overwrite)).is_equal_to(2);
+        overwriting.execute(false, |_| {})?;
+        Ok(())
+    }
+
+    #[test]
+    fn overwritten_files_are_kept_if_the_conversion_fails() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let source = dir.path().join("1.jpg");
+        fs_err::copy("./src/empty-100x200.jpg", &source)?;
+        let mut minutes = minutes();
+        minutes.sections[0].image_files = vec![SourceImagePath::new(source.clone())];
+        let layout = layout(&dir.path().join("out"), VARIANTS)?;
+        let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+        ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Fail)
+            .execute(false, |_| {})?;
+        let small = dir.path().join("out/session/1_small.jpg");
+        let converted = fs_err::read(&small)?;
+        fs_err::write(&source, "no image")?;
+
+        let report = ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Overwrite)
+            .execute(true, |_| {})?;
+
+        assert_that!(report.failed).has_length(1);
+        assert_that!(fs_err::read(&small)?).is_equal_to(converted);
+        Ok(())
+    }
+
+    #[test]
+    fn created_files_are_removed_if_another_variant_fails() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let minutes = minutes();
+        let layout = layout(&dir.path().join("out"), VARIANTS)?;
+        let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+        let plan = ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Fail);
+        let small = dir.path().join("out/session/empty-100x200_small.jpg");
+        let large = dir.path().join("out/session/empty-100x200_large.jpg");
+        fs_err::create_dir_all(dir.path().join("out/session"))?;
+        fs_err::write(&large, "created by someone else")?;
+
+        let report = plan.execute(true, |_| {})?;
+
+        assert_that!(report.failed).has_length(1);
+        assert_that!(small.exists()).is_false();
+        fs_err::remove_file(&large)?;
+        let rerun = ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Fail);
+        assert_that!(rerun.count(Action::Conflict)).is_equal_to(0);
+        Ok(())
+    }
+
+    #[test]
+    fn progress_and_failed_images_are_reported() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let broken = dir.path().join("broken.jpg");
+        fs_err::write(&broken, "no image")?;
+        let mut minutes = minutes();
+        minutes.sections[0]
+            .image_files
+            .insert(0, SourceImagePath::new(broken.clone()));
+        let layout = layout(&dir.path().join("out"), VARIANTS)?;
+        let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+        let plan = ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Fail);
+
+        let result = plan.execute(false, |_| {});
+        assert_that!(result.is_err()).is_true();
+        let mut progress = vec![];
+        let report = plan.execute(true, |p| {
+            progress.push((p.images_done, p.section_images_done, p.section_image_count))
+        })?;
+        let failed = report.failed;
+
+        assert_that!(progress).is_equal_to(vec![(1, 1, 2), (2, 2, 2)]);
+        assert_that!(report.stats.images).is_equal_to(1);
+        assert_that!(report.stats.files).is_equal_to(2);
+        assert_that!(report.stats.bytes_in).is_greater_than(0);
+        assert_that!(failed).has_length(1);
+        assert_that!(failed[0].source_image_path).is_equal_to(&broken);
+        assert_that!(failed[0].section_path).is_equal_to(vec!["session".to_string()]);
+        assert_that!(failed[0].error.section()).is_equal_to(Some("session"));
+        assert_that!(dir
+            .path()
+            .join("out/session/empty-100x200_large.jpg")
+            .is_file())
+        .is_true();
+        Ok(())
+    }
+}
@@ -0,0 +1,279 @@
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::error::Error;
+use crate::html_output::escape;
+
+/// Path the preview page polls to find out whether it has to reload.
+const VERSION_PATH: &str = "/__version";
+
+/// Serves the document as an HTML page at `/` and the generated images below it, so
+/// the minutes can be checked in a browser before they are published.
+pub struct PreviewServer {
+    server: Server,
+    output_root: PathBuf,
+    page: PreviewPage,
+}
+
+impl PreviewServer {
+    /// Listens on `port` of localhost, any free one for `0`.
+    pub fn bind(port: u16, output_root: &Path) -> Result<Self, Error> {
+        let server = Server::http(("127.0.0.1", port)).map_err(|err| Error::Listen {
+            port,
+            message: err.to_string(),
+        })?;
+        Ok(Self {
+            server,
+            output_root: output_root.to_path_buf(),
+            page: PreviewPage::default(),
+        })
+    }
+
+    /// Base URL of the server, to be used as online base path.
+    pub fn url(&self) -> String {
+        let address = self.server.server_addr().to_ip();
+        format!(
+            "http://{}",
+            address.expect("the server listens on a TCP port")
+        )
+    }
+
+    pub fn page(&self) -> PreviewPage {
+        self.page.clone()
+    }
+
+    /// Answers requests until the process ends.
+    pub fn run(self) {
+        for request in self.server.incoming_requests() {
+            let response = self.respond(&request);
+            // The browser may have gone away, which does not concern the other requests.
+            let _ = request.respond(response);
+        }
+    }
+
+    fn respond(&self, request: &Request) -> Response<Box<dyn std::io::Read + Send>> {
+        if ![Method::Get, Method::Head].contains(request.method()) {
+            return text_response(405, "method not allowed");
+        }
+        let path = request.url().split(['?', '#']).next().unwrap_or_default();
+        match path {
+            "/" | "/index.html" => {
+                let (version, body) = self.page.current();
+                Response::from_string(page_html(version, &body))
+                    .boxed()
+                    .with_header(content_type("text/html; charset=utf-8"))
+            }
+            VERSION_PATH => text_response(200, &self.page.current().0.to_string()),
+            _ => match self.file_path(path) {
+                Some(file_path) if file_path.is_file() => match fs_err::File::open(&file_path) {
+                    Ok(file) => Response::from_file(file.into_parts().0)
+                        .boxed()
+                        .with_header(content_type(media_type(&file_path))),
+                    Err(err) => text_response(500, &err.to_string()),
+                },
+                _ => text_response(404, "not found"),
+            },
+        }
+    }
+
+    /// The file below the output root that the URL path refers to, if any.
+    fn file_path(&self, url_path: &str) -> Option<PathBuf> {
+        let decoded = percent_encoding::percent_decode_str(url_path)
+            .decode_utf8()
+            .ok()?;
+        let relative = Path::new(decoded.trim_start_matches('/'));
+        relative
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)))
+            .then(|| self.output_root.join(relative))
+    }
+}
+
+/// The document shown by a [`PreviewServer`]. Browsers showing it reload when it is
+/// updated.
+#[derive(Clone, Default)]
+pub struct PreviewPage(Arc<Mutex<(u64, String)>>);
+
+impl PreviewPage {
+    /// Replaces the HTML body of the page.
+    pub fn update(&self, body: String) {
+        let mut page = self.0.lock().unwrap_or_else(|e| e.into_inner());
+        *page = (page.0 + 1, body);
+    }
+
+    fn current(&self) -> (u64, String) {
+        self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+}
+
+pub fn markdown_to_html(markdown: &str) -> String {
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, pulldown_cmark::Parser::new(markdown));
+    html
+}
+
+/// Body of the preview page for an error, e.g. while the minutes are rebuilt.
+pub fn error_html(message: &str) -> String {
+    format!("<h1>Error</h1>\n<pre>{}</pre>", escape(message))
+}
+
+fn page_html(version: u64, body: &str) -> String {
+    format!(
+        r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<title>Minutes preview</title>
+<style>body {{ font-family: sans-serif; max-width: 60em; margin: auto; }} img {{ max-width: 100%; height: auto; }}</style>
+</head>
+<body>
+{body}
+<script>
+setInterval(async () => {{
+  try {{
+    const response = await fetch("{VERSION_PATH}");
+    if ((await response.text()) !== "{version}") location.reload();
+  }} catch (e) {{}}
+}}, 1000);
+</script>
+</body>
+</html>
+"#
+    )
+}
+
+pub(crate) fn media_type(path: &Path) -> &'static str {
+    match path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .as_deref()
+    {
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("html") => "text/html; charset=utf-8",
+        Some("md") => "text/markdown; charset=utf-8",
+        Some("json") => "application/json",
+        _ => "application/octet-stream",
+    }
+}
+
+fn text_response(status: u16, text: &str) -> Response<Box<dyn std::io::Read + Send>> {
+    Response::from_string(text)
+        .boxed()
+        .with_status_code(status)
+        .with_header(content_type("text/plain; charset=utf-8"))
+        .with_header(header("Cache-Control", "no-store"))
+}
+
+fn content_type(value: &str) -> Header {
+    header("Content-Type", value)
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes())
+        .expect("header names and values are ASCII")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use speculoos::prelude::*;
+
+    use crate::conversion::test_support::{build_markdown, copy_test_images, layout};
+    use crate::preview::{markdown_to_html, PreviewServer};
+
+    fn get(base_url: &str, path: &str) -> anyhow::Result<String> {
+        let mut stream = TcpStream::connect(base_url.trim_start_matches("http://"))?;
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        )?;
+        let mut response = vec![];
+        stream.read_to_end(&mut response)?;
+        Ok(String::from_utf8_lossy(&response).into_owned())
+    }
+
+    #[test]
+    fn serves_the_page_and_the_generated_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs_err::create_dir(dir.path().join("section 1"))?;
+        fs_err::write(dir.path().join("section 1/1_small.jpg"), "jpeg")?;
+        fs_err::write(dir.path().join("secret.txt"), "secret")?;
+        let output_root = dir.path().join("section 1");
+        let server = PreviewServer::bind(0, &output_root)?;
+        let url = server.url();
+        let page = server.page();
+        page.update("<h1>section 1</h1>".to_string());
+        std::thread::spawn(move || server.run());
+
+        let index = get(&url, "/")?;
+        let image = get(&url, "/1_small.jpg")?;
+        let escaping = get(&url, "/../secret.txt")?;
+        let version_before = get(&url, "/__version")?;
+        page.update("<h1>section 2</h1>".to_string());
+        let version_after = get(&url, "/__version")?;
+
+        assert_that!(index).starts_with("HTTP/1.1 200");
+        assert_that!(index).contains("<h1>section 1</h1>");
+        assert_that!(image).starts_with("HTTP/1.1 200");
+        assert_that!(image).contains("Content-Type: image/jpeg");
+        assert_that!(image).ends_with("\r\n\r\njpeg");
+        assert_that!(escaping).starts_with("HTTP/1.1 404");
+        assert_that!(version_before).ends_with("\r\n\r\n1");
+        assert_that!(version_after).ends_with("\r\n\r\n2");
+        Ok(())
+    }
+
+    #[test]
+    fn decodes_file_names_with_spaces() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let server = PreviewServer::bind(0, dir.path())?;
+
+        assert_that!(server.file_path("/section%201/1_small.jpg"))
+            .is_equal_to(Some(dir.path().join("section 1/1_small.jpg")));
+        assert_that!(server.file_path("/section%201/%2E%2E/%2E%2E/etc/passwd")).is_none();
+        Ok(())
+    }
+
+    #[test]
+    fn renders_markdown_with_html_images() {
+        let html = markdown_to_html(
+            "# section 1\n\n<a href=\"/1_large.jpg\"><img src=\"/1_small.jpg\"></a>\n",
+        );
+
+        assert_that!(html).is_equal_to(
+            "<h1>section 1</h1>\n<p><a href=\"/1_large.jpg\"><img src=\"/1_small.jpg\"></a></p>\n"
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn shows_the_images_of_sections_with_spaces() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("input");
+        let output = dir.path().join("output");
+        copy_test_images(&input, &["Session 1/a.jpg"])?;
+        let layout = layout(&output, &["small=0.5", "large=0.5"])?;
+        let server = PreviewServer::bind(0, &output)?;
+        let url = server.url();
+        std::thread::spawn(move || server.run());
+
+        let html = markdown_to_html(&build_markdown(&input, &layout, &url)?);
+        let image = get(&url, "/Session%201/a_small.jpg")?;
+
+        assert_that!(html).contains(
+            format!(
+                "<img src=\"{}/Session%201/a_small.jpg\" alt=\"Session 1\" />",
+                url
+            )
+            .as_str(),
+        );
+        assert_that!(image).starts_with("HTTP/1.1 200");
+        Ok(())
+    }
+}
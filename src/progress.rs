@@ -0,0 +1,151 @@
+use std::io::{self, IsTerminal, Stderr, Write};
+use std::time::Duration;
+
+use crate::plan::{ConversionStats, Progress};
+
+/// Shows the progress of a conversion: on a terminal as a single line that is updated
+/// after each image, otherwise as one line per finished section.
+pub struct ProgressPrinter<W: Write> {
+    out: W,
+    interactive: bool,
+    line_open: bool,
+}
+
+impl ProgressPrinter<Stderr> {
+    pub fn stderr() -> Self {
+        let stderr = io::stderr();
+        let interactive = stderr.is_terminal();
+        Self::new(stderr, interactive)
+    }
+}
+
+impl<W: Write> ProgressPrinter<W> {
+    pub fn new(out: W, interactive: bool) -> Self {
+        Self {
+            out,
+            interactive,
+            line_open: false,
+        }
+    }
+
+    /// Progress is only informational, so failures to show it are ignored.
+    pub fn update(&mut self, progress: &Progress) {
+        let section = progress.section_path.join(" / ");
+        if self.interactive {
+            let _ = write!(
+                self.out,
+                "\r\x1b[K{}/{} images ({}%), {} {}/{}, {} left",
+                progress.images_done,
+                progress.image_count,
+                progress.images_done * 100 / progress.image_count.max(1),
+                section,
+                progress.section_images_done,
+                progress.section_image_count,
+                format_duration(progress.remaining())
+            );
+            let _ = self.out.flush();
+            self.line_open = true;
+        } else if progress.section_images_done == progress.section_image_count {
+            let _ = writeln!(
+                self.out,
+                "{}: {} images done, {}/{} in total, {} left",
+                section,
+                progress.section_image_count,
+                progress.images_done,
+                progress.image_count,
+                format_duration(progress.remaining())
+            );
+        }
+    }
+
+    /// Ends the progress line, so that the next output starts on a new line.
+    pub fn finish(&mut self) {
+        if self.line_open {
+            let _ = writeln!(self.out);
+            self.line_open = false;
+        }
+    }
+}
+
+/// One line summary of a conversion, e.g. for the end of a run.
+pub fn format_stats(stats: &ConversionStats) -> String {
+    format!(
+        "converted {} images to {} files in {}, {} in, {} out (decoding {}, resizing {}, encoding {})",
+        stats.images,
+        stats.files,
+        format_duration(stats.elapsed),
+        format_bytes(stats.bytes_in),
+        format_bytes(stats.bytes_out),
+        format_duration(stats.durations.decoding),
+        format_duration(stats.durations.resizing),
+        format_duration(stats.durations.encoding)
+    )
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        0..=59 => format!("{:.1}s", duration.as_secs_f64()),
+        60..=3599 => format!("{}m {:02}s", seconds / 60, seconds % 60),
+        _ => format!("{}h {:02}m", seconds / 3600, seconds % 3600 / 60),
+    }
+}
+
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["kB", "MB", "GB", "TB"];
+    if bytes < 1000 {
+        return format!("{} B", bytes);
+    }
+    let mut value = bytes as f64 / 1000.0;
+    let mut unit = 0;
+    while value >= 1000.0 && unit < UNITS.len() - 1 {
+        value /= 1000.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use speculoos::prelude::*;
+
+    use crate::plan::Progress;
+    use crate::progress::{format_bytes, format_duration, ProgressPrinter};
+
+    fn progress(section_path: &[String], section_images_done: usize) -> Progress<'_> {
+        Progress {
+            images_done: 3,
+            image_count: 6,
+            section_path,
+            section_images_done,
+            section_image_count: 3,
+            elapsed: Duration::from_secs(90),
+        }
+    }
+
+    #[test]
+    fn sizes_and_durations_are_readable() {
+        assert_that!(format_bytes(999)).is_equal_to("999 B".to_string());
+        assert_that!(format_bytes(1_234_567)).is_equal_to("1.2 MB".to_string());
+        assert_that!(format_duration(Duration::from_millis(4200))).is_equal_to("4.2s".to_string());
+        assert_that!(format_duration(Duration::from_secs(192))).is_equal_to("3m 12s".to_string());
+        assert_that!(format_duration(Duration::from_secs(3720))).is_equal_to("1h 02m".to_string());
+    }
+
+    #[test]
+    fn without_terminal_only_finished_sections_are_shown() -> anyhow::Result<()> {
+        let section_path = vec!["day 1".to_string(), "talk".to_string()];
+        let mut out = vec![];
+        let mut printer = ProgressPrinter::new(&mut out, false);
+
+        printer.update(&progress(&section_path, 2));
+        printer.update(&progress(&section_path, 3));
+        printer.finish();
+
+        assert_that!(String::from_utf8(out)?)
+            .is_equal_to("day 1 / talk: 3 images done, 3/6 in total, 1m 30s left\n".to_string());
+        Ok(())
+    }
+}
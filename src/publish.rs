@@ -0,0 +1,465 @@
+mod s3;
+mod sftp;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::{Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::atomic_file;
+use crate::error::Error;
+
+pub use s3::S3Target;
+pub use sftp::SftpTarget;
+
+/// File at the root of a target that lists the published files with the SHA-256 of
+/// their content, so that a later run uploads only what changed.
+pub const STATE_FILE_NAME: &str = ".minutes-publish.json";
+
+/// A place the output directory is published to. Paths are relative to its root and
+/// separated by `/`.
+pub trait PublishTarget {
+    /// Reads a file, `None` if it does not exist.
+    fn read(&mut self, path: &str) -> Result<Option<Vec<u8>>, Error>;
+
+    /// Uploads local files, creating directories as needed.
+    fn upload(&mut self, files: &[(String, PathBuf)]) -> Result<(), Error>;
+
+    /// Which of `paths` exist.
+    fn existing(&mut self, paths: &[String]) -> Result<BTreeSet<String>, Error>;
+}
+
+/// Where to publish to, as given in the config: a local directory, `sftp://` or `s3://`.
+#[derive(Serialize, Deserialize, Clone, Debug, Eq, PartialEq)]
+#[serde(try_from = "String", into = "String")]
+pub enum PublishTo {
+    Directory(PathBuf),
+    /// `sftp://[USER@]HOST[:PORT]/PATH`, uploaded with the `sftp` command, which takes
+    /// care of keys and known hosts.
+    Sftp {
+        user: Option<String>,
+        host: String,
+        port: Option<u16>,
+        path: String,
+    },
+    /// `s3://BUCKET[/PREFIX][?endpoint=URL&region=REGION]` with the credentials from
+    /// `AWS_ACCESS_KEY_ID` and `AWS_SECRET_ACCESS_KEY`.
+    S3 {
+        bucket: String,
+        prefix: String,
+        endpoint: Option<String>,
+        region: Option<String>,
+    },
+}
+
+impl PublishTo {
+    pub fn open(&self) -> Result<Box<dyn PublishTarget>, Error> {
+        Ok(match self {
+            PublishTo::Directory(root) => Box::new(DirectoryTarget { root: root.clone() }),
+            PublishTo::Sftp {
+                user,
+                host,
+                port,
+                path,
+            } => Box::new(SftpTarget::new(user.as_deref(), host, *port, path)),
+            PublishTo::S3 {
+                bucket,
+                prefix,
+                endpoint,
+                region,
+            } => Box::new(S3Target::from_env(
+                bucket,
+                prefix,
+                endpoint.as_deref(),
+                region.as_deref(),
+            )?),
+        })
+    }
+}
+
+impl FromStr for PublishTo {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(rest) = s.strip_prefix("sftp://") {
+            let (authority, path) = rest.split_once('/').unwrap_or((rest, ""));
+            let (user, host_and_port) = match authority.split_once('@') {
+                Some((user, host_and_port)) => (Some(user.to_string()), host_and_port),
+                None => (None, authority),
+            };
+            let (host, port) = match host_and_port.split_once(':') {
+                Some((host, port)) => (
+                    host,
+                    Some(
+                        port.parse()
+                            .map_err(|_| format!("invalid port {} in {}", port, s))?,
+                    ),
+                ),
+                None => (host_and_port, None),
+            };
+            if host.is_empty() {
+                return Err(format!("no host in {}", s));
+            }
+            Ok(PublishTo::Sftp {
+                user,
+                host: host.to_string(),
+                port,
+                path: format!("/{}", path.trim_end_matches('/')),
+            })
+        } else if let Some(rest) = s.strip_prefix("s3://") {
+            let (location, query) = rest.split_once('?').unwrap_or((rest, ""));
+            let (bucket, prefix) = location.split_once('/').unwrap_or((location, ""));
+            if bucket.is_empty() {
+                return Err(format!("no bucket in {}", s));
+            }
+            let mut endpoint = None;
+            let mut region = None;
+            for parameter in query.split('&').filter(|p| !p.is_empty()) {
+                match parameter.split_once('=') {
+                    Some(("endpoint", value)) => endpoint = Some(value.to_string()),
+                    Some(("region", value)) => region = Some(value.to_string()),
+                    _ => {
+                        return Err(format!(
+                            "unknown parameter {} in {}, expected endpoint or region",
+                            parameter, s
+                        ))
+                    }
+                }
+            }
+            Ok(PublishTo::S3 {
+                bucket: bucket.to_string(),
+                prefix: prefix.trim_matches('/').to_string(),
+                endpoint,
+                region,
+            })
+        } else if s.contains("://") {
+            Err(format!(
+                "unknown publish target {}, expected a directory, sftp:// or s3://",
+                s
+            ))
+        } else {
+            Ok(PublishTo::Directory(PathBuf::from(s)))
+        }
+    }
+}
+
+impl TryFrom<String> for PublishTo {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for PublishTo {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PublishTo::Directory(root) => write!(f, "{}", root.display()),
+            PublishTo::Sftp {
+                user,
+                host,
+                port,
+                path,
+            } => {
+                write!(f, "sftp://")?;
+                if let Some(user) = user {
+                    write!(f, "{}@", user)?;
+                }
+                write!(f, "{}", host)?;
+                if let Some(port) = port {
+                    write!(f, ":{}", port)?;
+                }
+                write!(f, "{}", path)
+            }
+            PublishTo::S3 {
+                bucket,
+                prefix,
+                endpoint,
+                region,
+            } => {
+                write!(f, "s3://{}", bucket)?;
+                if !prefix.is_empty() {
+                    write!(f, "/{}", prefix)?;
+                }
+                let parameters: Vec<_> = [("endpoint", endpoint), ("region", region)]
+                    .into_iter()
+                    .filter_map(|(name, value)| value.as_ref().map(|v| format!("{}={}", name, v)))
+                    .collect();
+                if !parameters.is_empty() {
+                    write!(f, "?{}", parameters.join("&"))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl From<PublishTo> for String {
+    fn from(value: PublishTo) -> Self {
+        value.to_string()
+    }
+}
+
+/// What [`publish`] did.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct PublishReport {
+    pub uploaded: Vec<String>,
+    pub unchanged: usize,
+    pub bytes: u64,
+}
+
+/// Uploads the files below `output_root` that are not at the target with the same
+/// content yet, then checks that every one of `urls` (below `online_base_path`)
+/// refers to a file at the target. Hidden files, e.g. left-overs of killed runs, are
+/// not published, and files at the target are never deleted.
+pub fn publish(
+    output_root: &Path,
+    target: &mut dyn PublishTarget,
+    online_base_path: &str,
+    urls: &[String],
+) -> Result<PublishReport, Error> {
+    let local = local_files(output_root)?;
+    let mut state: BTreeMap<String, String> = match target.read(STATE_FILE_NAME)? {
+        Some(content) => serde_json::from_slice(&content)
+            .map_err(|err| Error::parse(err).in_file(Path::new(STATE_FILE_NAME)))?,
+        None => BTreeMap::new(),
+    };
+    let mut report = PublishReport::default();
+    let mut changed = vec![];
+    for (path, local_path) in local {
+        let hash = content_hash(&local_path)?;
+        if state.get(&path) == Some(&hash) {
+            report.unchanged += 1;
+            continue;
+        }
+        report.bytes += fs_err::metadata(&local_path)
+            .map_err(Error::read(&local_path))?
+            .len();
+        changed.push((path.clone(), local_path));
+        state.insert(path, hash);
+    }
+    if !changed.is_empty() {
+        target.upload(&changed)?;
+        let state_file = tempfile::tempdir().map_err(Error::write(std::env::temp_dir()))?;
+        let state_path = state_file.path().join(STATE_FILE_NAME);
+        atomic_file::create_new(&state_path, |writer| {
+            serde_json::to_writer_pretty(&mut *writer, &state)
+                .map_err(|e| Error::write(&state_path)(io::Error::from(e)))
+        })?;
+        target.upload(&[(STATE_FILE_NAME.to_string(), state_path)])?;
+    }
+    report.uploaded = changed.into_iter().map(|(path, _)| path).collect();
+
+    let prefix = format!("{}/", online_base_path.trim_end_matches('/'));
+    let mut unresolved: Vec<String> = vec![];
+    let mut paths = vec![];
+    for url in urls {
+        match url.strip_prefix(&prefix) {
+            Some(path) => paths.push((
+                url,
+                percent_decode_str(path).decode_utf8_lossy().to_string(),
+            )),
+            None => unresolved.push(url.clone()),
+        }
+    }
+    let existing = target.existing(&paths.iter().map(|(_, p)| p.clone()).collect::<Vec<_>>())?;
+    unresolved.extend(
+        paths
+            .into_iter()
+            .filter(|(_, path)| !existing.contains(path))
+            .map(|(url, _)| url.clone()),
+    );
+    if !unresolved.is_empty() {
+        return Err(Error::Unpublished { urls: unresolved });
+    }
+    Ok(report)
+}
+
+/// Files below `root` by their path relative to it, without hidden files.
+pub(crate) fn local_files(root: &Path) -> Result<BTreeMap<String, PathBuf>, Error> {
+    let mut files = BTreeMap::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        for entry in fs_err::read_dir(&dir).map_err(Error::read(&dir))? {
+            let path = entry.map_err(Error::read(&dir))?.path();
+            if path
+                .file_name()
+                .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+            {
+                continue;
+            }
+            if path.is_dir() {
+                dirs.push(path);
+            } else if let Ok(relative) = path.strip_prefix(root) {
+                let relative = relative
+                    .iter()
+                    .map(|c| c.to_string_lossy())
+                    .collect::<Vec<_>>()
+                    .join("/");
+                files.insert(relative, path);
+            }
+        }
+    }
+    Ok(files)
+}
+
+fn content_hash(path: &Path) -> Result<String, Error> {
+    let mut hasher = Sha256::new();
+    io::copy(
+        &mut fs_err::File::open(path).map_err(Error::read(path))?,
+        &mut hasher,
+    )
+    .map_err(Error::read(path))?;
+    Ok(hex(&hasher.finalize()))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Mirrors the output into a local directory, e.g. one served by a web server or
+/// synchronized elsewhere.
+pub struct DirectoryTarget {
+    pub root: PathBuf,
+}
+
+impl PublishTarget for DirectoryTarget {
+    fn read(&mut self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        let path = self.root.join(path);
+        match fs_err::read(&path) {
+            Ok(content) => Ok(Some(content)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(Error::read(&path)(err)),
+        }
+    }
+
+    fn upload(&mut self, files: &[(String, PathBuf)]) -> Result<(), Error> {
+        for (path, local_path) in files {
+            let mut source = fs_err::File::open(local_path).map_err(Error::read(local_path))?;
+            let path = self.root.join(path);
+            atomic_file::replace(&path, |file| {
+                io::copy(&mut source, file)
+                    .map(|_| ())
+                    .map_err(Error::write(&path))
+            })?;
+        }
+        Ok(())
+    }
+
+    fn existing(&mut self, paths: &[String]) -> Result<BTreeSet<String>, Error> {
+        Ok(paths
+            .iter()
+            .filter(|path| self.root.join(path).is_file())
+            .cloned()
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use speculoos::prelude::*;
+
+    use crate::error::Error;
+    use crate::publish::{publish, DirectoryTarget, PublishTo, STATE_FILE_NAME};
+
+    #[test]
+    fn parses_publish_targets() -> anyhow::Result<()> {
+        let sftp: PublishTo = "sftp://minutes@example.org:2222/var/www/minutes/"
+            .parse()
+            .map_err(anyhow::Error::msg)?;
+        let s3: PublishTo = "s3://minutes/2024/?endpoint=http://localhost:9000&region=eu-central-1"
+            .parse()
+            .map_err(anyhow::Error::msg)?;
+
+        assert_that!(sftp).is_equal_to(PublishTo::Sftp {
+            user: Some("minutes".to_string()),
+            host: "example.org".to_string(),
+            port: Some(2222),
+            path: "/var/www/minutes".to_string(),
+        });
+        assert_that!(s3).is_equal_to(PublishTo::S3 {
+            bucket: "minutes".to_string(),
+            prefix: "2024".to_string(),
+            endpoint: Some("http://localhost:9000".to_string()),
+            region: Some("eu-central-1".to_string()),
+        });
+        assert_that!("/srv/minutes".parse::<PublishTo>())
+            .is_ok_containing(PublishTo::Directory(PathBuf::from("/srv/minutes")));
+        assert_that!(s3.to_string().parse::<PublishTo>()).is_ok_containing(s3);
+        assert_that!("ftp://example.org".parse::<PublishTo>()).is_err();
+        Ok(())
+    }
+
+    #[test]
+    fn uploads_only_changed_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("output");
+        fs_err::create_dir_all(output.join("section"))?;
+        fs_err::write(output.join("section/1_small.jpg"), "1")?;
+        fs_err::write(output.join("section/2_small.jpg"), "2")?;
+        fs_err::write(output.join("section/.3_small.jpg.abc.part"), "3")?;
+        let mut target = DirectoryTarget {
+            root: dir.path().join("published"),
+        };
+        let urls = vec![
+            "/minutes/section/1_small.jpg".to_string(),
+            "/minutes/section/2_small.jpg".to_string(),
+        ];
+
+        let first = publish(&output, &mut target, "/minutes", &urls)?;
+        fs_err::write(output.join("section/2_small.jpg"), "two")?;
+        let second = publish(&output, &mut target, "/minutes", &urls)?;
+
+        assert_that!(first.uploaded).is_equal_to(vec![
+            "section/1_small.jpg".to_string(),
+            "section/2_small.jpg".to_string(),
+        ]);
+        assert_that!(second.uploaded).is_equal_to(vec!["section/2_small.jpg".to_string()]);
+        assert_that!(second.unchanged).is_equal_to(1);
+        assert_that!(second.bytes).is_equal_to(3);
+        assert_that!(fs_err::read_to_string(
+            dir.path().join("published/section/2_small.jpg")
+        )?)
+        .is_equal_to("two".to_string());
+        assert_that!(dir.path().join("published").join(STATE_FILE_NAME).is_file()).is_true();
+        assert_that!(dir
+            .path()
+            .join("published/section/.3_small.jpg.abc.part")
+            .exists())
+        .is_false();
+        Ok(())
+    }
+
+    #[test]
+    fn fails_for_urls_without_published_file() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("output");
+        fs_err::create_dir_all(&output)?;
+        fs_err::write(output.join("1_small.jpg"), "1")?;
+        let mut target = DirectoryTarget {
+            root: dir.path().join("published"),
+        };
+        let urls = vec![
+            "/minutes/1_small.jpg".to_string(),
+            "/minutes/1_large.jpg".to_string(),
+            "/elsewhere/1_small.jpg".to_string(),
+        ];
+
+        let result = publish(&output, &mut target, "/minutes", &urls);
+
+        let err = assert_that!(result).is_err().subject;
+        assert_that!(matches!(err, Error::Unpublished { urls } if urls == &vec![
+            "/elsewhere/1_small.jpg".to_string(),
+            "/minutes/1_large.jpg".to_string(),
+        ]))
+        .is_true();
+        Ok(())
+    }
+}
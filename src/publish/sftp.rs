@@ -0,0 +1,175 @@
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::error::Error;
+use crate::publish::PublishTarget;
+
+/// A directory on an SFTP server, reached with the `sftp` command of OpenSSH in batch
+/// mode, so that its configuration, keys and known hosts apply.
+pub struct SftpTarget {
+    destination: String,
+    port: Option<u16>,
+    root: String,
+}
+
+impl SftpTarget {
+    pub fn new(user: Option<&str>, host: &str, port: Option<u16>, root: &str) -> Self {
+        Self {
+            destination: match user {
+                Some(user) => format!("{}@{}", user, host),
+                None => host.to_string(),
+            },
+            port,
+            root: root.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn remote_path(&self, path: &str) -> String {
+        format!("{}/{}", self.root, path)
+    }
+
+    /// Runs the batch `commands`, of which those starting with `-` may fail, and returns
+    /// the standard output without the echoed commands.
+    fn run(&self, commands: &[String]) -> Result<String, Error> {
+        let mut command = Command::new("sftp");
+        command.args(["-q", "-b", "-"]);
+        if let Some(port) = self.port {
+            command.args(["-P", &port.to_string()]);
+        }
+        let mut child = command
+            .arg(&self.destination)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|err| self.error(format!("could not run sftp: {}", err)))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(format!("{}\n", commands.join("\n")).as_bytes())
+                .map_err(|err| self.error(err.to_string()))?;
+        }
+        let output = child
+            .wait_with_output()
+            .map_err(|err| self.error(err.to_string()))?;
+        if !output.status.success() {
+            return Err(self.error(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .filter(|line| !line.starts_with("sftp>"))
+            .collect::<Vec<_>>()
+            .join("\n"))
+    }
+
+    fn error(&self, message: String) -> Error {
+        Error::Publish {
+            target: format!("sftp://{}{}", self.destination, self.root),
+            message,
+        }
+    }
+}
+
+impl PublishTarget for SftpTarget {
+    fn read(&mut self, path: &str) -> Result<Option<Vec<u8>>, Error> {
+        let dir = tempfile::tempdir().map_err(Error::write(std::env::temp_dir()))?;
+        let local_path = dir.path().join("download");
+        self.run(&[format!(
+            "-get {} {}",
+            quote(&self.remote_path(path)),
+            quote(&local_path.to_string_lossy())
+        )])?;
+        if !local_path.is_file() {
+            return Ok(None);
+        }
+        Ok(Some(
+            fs_err::read(&local_path).map_err(Error::read(&local_path))?,
+        ))
+    }
+
+    fn upload(&mut self, files: &[(String, PathBuf)]) -> Result<(), Error> {
+        self.run(&upload_commands(&self.root, files))?;
+        Ok(())
+    }
+
+    fn existing(&mut self, paths: &[String]) -> Result<BTreeSet<String>, Error> {
+        let commands: Vec<_> = paths
+            .iter()
+            .map(|path| format!("-ls -1 {}", quote(&self.remote_path(path))))
+            .collect();
+        let listed: BTreeSet<String> = self
+            .run(&commands)?
+            .lines()
+            .map(|line| line.trim().to_string())
+            .collect();
+        Ok(paths
+            .iter()
+            .filter(|path| listed.contains(&self.remote_path(path)))
+            .cloned()
+            .collect())
+    }
+}
+
+/// Creates the missing directories, which fails for existing ones, and puts the files.
+fn upload_commands(root: &str, files: &[(String, PathBuf)]) -> Vec<String> {
+    let dirs: BTreeSet<String> = files
+        .iter()
+        .flat_map(|(path, _)| {
+            let parts: Vec<_> = path.split('/').collect();
+            (1..parts.len()).map(move |n| parts[..n].join("/"))
+        })
+        .collect();
+    dirs.iter()
+        .map(|dir| format!("-mkdir {}", quote(&format!("{}/{}", root, dir))))
+        .chain(files.iter().map(|(path, local_path)| {
+            format!(
+                "put {} {}",
+                quote(&local_path.to_string_lossy()),
+                quote(&format!("{}/{}", root, path))
+            )
+        }))
+        .collect()
+}
+
+/// Arguments of batch commands are split at spaces unless quoted.
+fn quote(argument: &str) -> String {
+    format!(
+        "\"{}\"",
+        argument.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use speculoos::prelude::*;
+
+    use crate::publish::sftp::upload_commands;
+
+    #[test]
+    fn creates_parent_directories_before_uploading() {
+        let commands = upload_commands(
+            "/var/www/minutes",
+            &[
+                (
+                    "track/session 1/1_small.jpg".to_string(),
+                    PathBuf::from("/out/track/session 1/1_small.jpg"),
+                ),
+                (
+                    "track/2_small.jpg".to_string(),
+                    PathBuf::from("/out/track/2_small.jpg"),
+                ),
+            ],
+        );
+
+        assert_that!(commands).is_equal_to(vec![
+            "-mkdir \"/var/www/minutes/track\"".to_string(),
+            "-mkdir \"/var/www/minutes/track/session 1\"".to_string(),
+            "put \"/out/track/session 1/1_small.jpg\" \"/var/www/minutes/track/session 1/1_small.jpg\""
+                .to_string(),
+            "put \"/out/track/2_small.jpg\" \"/var/www/minutes/track/2_small.jpg\"".to_string(),
+        ]);
+    }
+}
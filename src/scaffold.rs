@@ -0,0 +1,107 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::CONFIG_FILE_NAME;
+use crate::error::Error;
+use crate::overrides::OVERRIDES_FILE_NAME;
+
+const EXAMPLE_SECTION_NAME: &str = "session 1";
+
+const EXAMPLE_CONFIG: &str = r#"# Settings of the minutes, options given on the command line or as MINUTES_*
+# environment variables take precedence. Relative paths are relative to this file.
+
+# output = "../output"
+# online-base-path = "https://example.org/minutes"
+
+thumbnail-ratio = 0.3
+# thumbnail-filter = "lanczos3"
+
+# Instead of thumbnail-ratio, any list of variants can be generated:
+# variants = ["thumb=320w", "medium=1024w:lanczos3", "large=original"]
+# thumbnail-variant = "thumb"
+# link-variant = "large"
+
+# format = "html"
+# sizes = "100vw"
+# html-images = true
+# document = "../minutes.md"
+# manifest = "../manifest.json"
+# naming = "{section_path}/{stem}_{variant}.{ext}"
+# existing = "skip"
+# introduction-title = "General"
+
+# Treat this directory as a flat folder of photos split into sections by capture time:
+# group-by-gap = 30
+
+# Upload the output directory after building, to a directory, sftp:// or s3://:
+# publish-to = "sftp://user@example.org/var/www/minutes"
+"#;
+
+const EXAMPLE_OVERRIDES: &str = r#"# Corrections of individual images of this section by file name.
+
+# ["image_1.jpg"]
+# rotate = 90              # clockwise, replaces the EXIF orientation
+# crop = [0, 0, 800, 600]  # x, y, width, height of the correctly oriented image
+
+# ["image_2.jpg"]
+# skip = true              # leave the image out of the minutes
+"#;
+
+/// Creates an input directory with an example section and commented example metadata
+/// files. Existing files are never overwritten. Returns the created files.
+pub fn create_input_scaffold(dir: &Path) -> Result<Vec<PathBuf>, Error> {
+    let section_dir = dir.join(EXAMPLE_SECTION_NAME);
+    let files = [
+        (dir.join(CONFIG_FILE_NAME), EXAMPLE_CONFIG),
+        (section_dir.join(OVERRIDES_FILE_NAME), EXAMPLE_OVERRIDES),
+    ];
+    if let Some((existing, _)) = files.iter().find(|(path, _)| path.exists()) {
+        return Err(Error::DestinationExists {
+            path: existing.clone(),
+        });
+    }
+    fs_err::create_dir_all(&section_dir).map_err(Error::write(&section_dir))?;
+    for (path, content) in &files {
+        fs_err::write(path, content).map_err(Error::write(path))?;
+    }
+    Ok(files.into_iter().map(|(path, _)| path).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use speculoos::prelude::*;
+
+    use crate::config::{Config, CONFIG_FILE_NAME};
+    use crate::input::{InputOptions, Minutes};
+    use crate::overrides::SectionOverrides;
+    use crate::scaffold::{create_input_scaffold, EXAMPLE_SECTION_NAME};
+
+    #[test]
+    fn scaffold_is_a_valid_input_directory() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+
+        let files = create_input_scaffold(dir.path())?;
+
+        assert_that!(files).has_length(2);
+        let config = Config::read(&dir.path().join(CONFIG_FILE_NAME))?;
+        assert_that!(config.thumbnail_ratio).is_equal_to(Some(0.3));
+        SectionOverrides::read_from_dir(dir.path().join(EXAMPLE_SECTION_NAME))?;
+        let minutes = Minutes::read(dir.path(), &InputOptions::default())?;
+        assert_that!(minutes.sections).has_length(1);
+        assert_that!(minutes.ignored_files).is_empty();
+        Ok(())
+    }
+
+    #[test]
+    fn existing_files_are_not_overwritten() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs_err::write(dir.path().join(CONFIG_FILE_NAME), "format = \"html\"\n")?;
+
+        let result = create_input_scaffold(dir.path());
+
+        let err = assert_that!(result).is_err().subject.to_string();
+        assert_that!(err).contains("exists already");
+        assert_that!(fs_err::read_to_string(dir.path().join(CONFIG_FILE_NAME))?)
+            .is_equal_to("format = \"html\"\n".to_string());
+        Ok(())
+    }
+}
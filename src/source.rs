@@ -0,0 +1,213 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Debug;
+use std::io::{BufRead, BufReader, Cursor, Seek};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use crate::archive::{Archive, ArchiveKind};
+use crate::error::Error;
+
+/// Content of a source image that can be decoded directly.
+pub trait ImageReader: BufRead + Seek {}
+
+impl<T: BufRead + Seek> ImageReader for T {}
+
+/// The directories and files directly within a directory of a [`MinutesSource`].
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct Listing {
+    pub dirs: Vec<PathBuf>,
+    pub files: Vec<PathBuf>,
+}
+
+/// Where the sections (directories) and images (files) of the minutes are read from.
+///
+/// All paths are relative to the source, the top-level directory is the empty path.
+/// Listed paths include the directory they are listed for, e.g. `session/1.jpg`.
+pub trait MinutesSource: Debug + Send + Sync {
+    /// Location of the source, used to show paths of images and ignored files.
+    fn root(&self) -> &Path;
+
+    fn list(&self, dir: &Path) -> Result<Listing, Error>;
+
+    fn open(&self, file: &Path) -> Result<Box<dyn ImageReader>, Error>;
+
+    fn contains_file(&self, file: &Path) -> Result<bool, Error> {
+        let dir = file.parent().unwrap_or(Path::new(""));
+        Ok(self.list(dir)?.files.iter().any(|f| f == file))
+    }
+}
+
+/// Opens `path` as an archive source if it is a `.zip`, `.tar`, `.tar.gz` or `.tgz`
+/// file and as a directory otherwise.
+pub fn open_source(path: &Path) -> Result<Arc<dyn MinutesSource>, Error> {
+    if path.is_file() && ArchiveKind::of(path).is_some() {
+        return Ok(Arc::new(Archive::open(path)?));
+    }
+    Ok(Arc::new(FileSystemSource::new(path.to_path_buf())))
+}
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct FileSystemSource {
+    root: PathBuf,
+}
+
+impl FileSystemSource {
+    pub fn new(root: PathBuf) -> Self {
+        Self { root }
+    }
+}
+
+impl MinutesSource for FileSystemSource {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn list(&self, dir: &Path) -> Result<Listing, Error> {
+        let mut listing = Listing::default();
+        let dir_path = self.root.join(dir);
+        for entry in fs_err::read_dir(&dir_path).map_err(Error::read(&dir_path))? {
+            let entry = entry.map_err(Error::read(&dir_path))?;
+            let path = dir.join(entry.file_name());
+            if entry.file_type().map_err(Error::read(&dir_path))?.is_dir() {
+                listing.dirs.push(path);
+            } else {
+                listing.files.push(path);
+            }
+        }
+        listing.dirs.sort();
+        listing.files.sort();
+        Ok(listing)
+    }
+
+    fn open(&self, file: &Path) -> Result<Box<dyn ImageReader>, Error> {
+        let path = self.root.join(file);
+        let file = fs_err::File::open(&path).map_err(Error::read(path))?;
+        Ok(Box::new(BufReader::new(file)))
+    }
+
+    fn contains_file(&self, file: &Path) -> Result<bool, Error> {
+        Ok(self.root.join(file).is_file())
+    }
+}
+
+/// Images kept in memory, e.g. received from an upload form.
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct InMemorySource {
+    root: PathBuf,
+    files: BTreeMap<PathBuf, Arc<[u8]>>,
+}
+
+impl InMemorySource {
+    /// `root` is only used to show the paths of the images, e.g. `upload`.
+    pub fn new(root: PathBuf) -> Self {
+        Self {
+            root,
+            files: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a file, e.g. `session 1/1.jpg`. Directories are created implicitly.
+    pub fn insert(&mut self, path: impl Into<PathBuf>, content: impl Into<Vec<u8>>) {
+        self.files.insert(path.into(), content.into().into());
+    }
+}
+
+impl MinutesSource for InMemorySource {
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    fn list(&self, dir: &Path) -> Result<Listing, Error> {
+        Ok(list_file_paths(self.files.keys(), dir))
+    }
+
+    fn open(&self, file: &Path) -> Result<Box<dyn ImageReader>, Error> {
+        let content = self
+            .files
+            .get(file)
+            .cloned()
+            .ok_or_else(|| Error::SourceMissing {
+                path: self.root.join(file),
+            })?;
+        Ok(Box::new(Cursor::new(content)))
+    }
+
+    fn contains_file(&self, file: &Path) -> Result<bool, Error> {
+        Ok(self.files.contains_key(file))
+    }
+}
+
+/// Lists the directories and files directly within `dir` for sources that only know
+/// the paths of their files.
+pub fn list_file_paths<'a>(paths: impl IntoIterator<Item = &'a PathBuf>, dir: &Path) -> Listing {
+    let mut dirs = BTreeSet::new();
+    let mut files = vec![];
+    for path in paths {
+        let Ok(relative) = path.strip_prefix(dir) else {
+            continue;
+        };
+        let mut components = relative.components();
+        match (components.next(), components.next()) {
+            (Some(first), Some(_)) => {
+                dirs.insert(dir.join(first));
+            }
+            (Some(_), None) => files.push(path.clone()),
+            _ => {}
+        }
+    }
+    files.sort();
+    Listing {
+        dirs: dirs.into_iter().collect(),
+        files,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::path::{Path, PathBuf};
+
+    use speculoos::prelude::*;
+
+    use crate::source::{FileSystemSource, InMemorySource, Listing, MinutesSource};
+
+    #[test]
+    fn in_memory_source_lists_implicit_directories() -> anyhow::Result<()> {
+        let mut source = InMemorySource::new(PathBuf::from("upload"));
+        source.insert("group.jpg", "a");
+        source.insert("track/session/1.jpg", "b");
+        source.insert("track/2.jpg", "c");
+
+        assert_that!(source.list(Path::new(""))?).is_equal_to(Listing {
+            dirs: vec![PathBuf::from("track")],
+            files: vec![PathBuf::from("group.jpg")],
+        });
+        assert_that!(source.list(Path::new("track"))?).is_equal_to(Listing {
+            dirs: vec![PathBuf::from("track/session")],
+            files: vec![PathBuf::from("track/2.jpg")],
+        });
+        let mut content = String::new();
+        source
+            .open(Path::new("track/session/1.jpg"))?
+            .read_to_string(&mut content)?;
+        assert_that!(content).is_equal_to("b".to_string());
+        assert_that!(source.open(Path::new("missing.jpg")).is_err()).is_true();
+        Ok(())
+    }
+
+    #[test]
+    fn file_system_source_lists_relative_paths() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs_err::create_dir(dir.path().join("session"))?;
+        fs_err::write(dir.path().join("session").join("1.jpg"), "")?;
+
+        let source = FileSystemSource::new(dir.path().to_path_buf());
+
+        assert_that!(source.list(Path::new("session"))?).is_equal_to(Listing {
+            dirs: vec![],
+            files: vec![PathBuf::from("session").join("1.jpg")],
+        });
+        assert_that!(source.contains_file(&PathBuf::from("session").join("1.jpg"))?).is_true();
+        Ok(())
+    }
+}
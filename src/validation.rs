@@ -0,0 +1,477 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::path::PathBuf;
+
+use crate::conversion::OutputLayout;
+use crate::image_operations::SourceImage;
+use crate::images::SourceImagePath;
+use crate::input::{Minutes, Section};
+use crate::naming::ImageNamingContext;
+use crate::overrides::{Crop, OVERRIDES_FILE_NAME};
+
+/// Images with a side shorter than this are probably icons or thumbnails by accident.
+const MIN_SIDE: u32 = 64;
+
+/// Images with more pixels than this take very long to convert.
+const MAX_PIXELS: u64 = 100_000_000;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum Problem {
+    MissingExtension {
+        image: PathBuf,
+    },
+    Unreadable {
+        image: PathBuf,
+        reason: String,
+    },
+    Undecodable {
+        image: PathBuf,
+        reason: String,
+    },
+    /// The naming scheme cannot create the output file names.
+    Unnameable {
+        image: PathBuf,
+        reason: String,
+    },
+    /// `image` would be written to the same file as `other_image`, which comes earlier.
+    NameCollision {
+        image: PathBuf,
+        output_path: PathBuf,
+        other_image: PathBuf,
+    },
+    EmptySection,
+    SuspiciousSize {
+        image: PathBuf,
+        width: u32,
+        height: u32,
+    },
+    /// The crop override is not inside the correctly oriented image.
+    CropOutside {
+        image: PathBuf,
+        crop: Crop,
+        width: u32,
+        height: u32,
+    },
+    /// An entry of the overrides file names no file of the section, e.g. a typo.
+    UnmatchedOverride {
+        key: String,
+    },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingExtension { image } => {
+                write!(f, "{}: has no file extension", image.to_string_lossy())
+            }
+            Problem::Unreadable { image, reason } => {
+                write!(f, "{}: cannot be read: {}", image.to_string_lossy(), reason)
+            }
+            Problem::Undecodable { image, reason } => write!(
+                f,
+                "{}: is not a valid image: {}",
+                image.to_string_lossy(),
+                reason
+            ),
+            Problem::Unnameable { image, reason } => write!(
+                f,
+                "{}: no output file name can be created: {}",
+                image.to_string_lossy(),
+                reason
+            ),
+            Problem::NameCollision {
+                image,
+                output_path,
+                other_image,
+            } => write!(
+                f,
+                "{}: would be written to {} like {}, change --naming",
+                image.to_string_lossy(),
+                output_path.to_string_lossy(),
+                other_image.to_string_lossy()
+            ),
+            Problem::EmptySection => write!(f, "contains no images"),
+            Problem::SuspiciousSize {
+                image,
+                width,
+                height,
+            } => write!(
+                f,
+                "{}: has a suspicious size of {}x{} pixels",
+                image.to_string_lossy(),
+                width,
+                height
+            ),
+            Problem::CropOutside {
+                image,
+                crop,
+                width,
+                height,
+            } => write!(
+                f,
+                "{}: crop [{}, {}, {}, {}] is outside of the image of {}x{} pixels",
+                image.to_string_lossy(),
+                crop.x,
+                crop.y,
+                crop.width,
+                crop.height,
+                width,
+                height
+            ),
+            Problem::UnmatchedOverride { key } => write!(
+                f,
+                "{}: [\"{}\"] matches no file of the section",
+                OVERRIDES_FILE_NAME, key
+            ),
+        }
+    }
+}
+
+/// The problems of one section, not including those of its subsections.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct SectionProblems {
+    /// Names of the sections from the top level down to the section.
+    pub section_path: Vec<String>,
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct ValidationReport {
+    pub image_count: usize,
+    pub section_count: usize,
+    /// Only sections that have problems, in document order.
+    pub sections: Vec<SectionProblems>,
+}
+
+impl ValidationReport {
+    pub fn is_ok(&self) -> bool {
+        self.sections.is_empty()
+    }
+
+    pub fn problem_count(&self) -> usize {
+        self.sections.iter().map(|s| s.problems.len()).sum()
+    }
+}
+
+impl Display for ValidationReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for section in &self.sections {
+            let names: Vec<_> = section
+                .section_path
+                .iter()
+                .map(|name| if name.is_empty() { "(untitled)" } else { name })
+                .collect();
+            writeln!(f, "{}:", names.join(" / "))?;
+            for problem in &section.problems {
+                writeln!(f, "  - {}", problem)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Looks at every image of `minutes` and collects all problems instead of stopping at
+/// the first one. Every image is decoded, JPEG images at the smallest scale.
+pub fn validate(minutes: &Minutes, layout: &OutputLayout) -> ValidationReport {
+    let mut validation = Validation {
+        layout,
+        output_paths: HashMap::new(),
+        report: ValidationReport::default(),
+    };
+    minutes
+        .sections
+        .iter()
+        .for_each(|s| validation.validate_section(s, &[]));
+    validation.report
+}
+
+struct Validation<'a> {
+    layout: &'a OutputLayout,
+    /// Source image of each output path created so far.
+    output_paths: HashMap<PathBuf, PathBuf>,
+    report: ValidationReport,
+}
+
+impl Validation<'_> {
+    fn validate_section(&mut self, section: &Section, parent_names: &[&str]) {
+        let mut section_path = parent_names.to_vec();
+        section_path.push(&section.name);
+        let mut problems = vec![];
+        if section.image_files.is_empty() && section.subsections.is_empty() {
+            problems.push(Problem::EmptySection);
+        }
+        problems.extend(
+            section
+                .overrides
+                .unmatched_keys()
+                .iter()
+                .map(|key| Problem::UnmatchedOverride { key: key.clone() }),
+        );
+        for (index, image) in section.image_files.iter().enumerate() {
+            let context = ImageNamingContext {
+                section_path: &section_path,
+                index: index + 1,
+                source: image,
+            };
+            problems.extend(self.validate_image(image, section, &context));
+        }
+        self.report.image_count += section.image_files.len();
+        self.report.section_count += 1;
+        if !problems.is_empty() {
+            self.report.sections.push(SectionProblems {
+                section_path: section_path.iter().map(|s| s.to_string()).collect(),
+                problems,
+            });
+        }
+        section
+            .subsections
+            .iter()
+            .for_each(|s| self.validate_section(s, &section_path));
+    }
+
+    fn validate_image(
+        &mut self,
+        image: &SourceImagePath,
+        section: &Section,
+        context: &ImageNamingContext,
+    ) -> Vec<Problem> {
+        let path = image.path().to_path_buf();
+        if image.extension().is_err() {
+            return vec![Problem::MissingExtension { image: path }];
+        }
+        if let Err(err) = image.open() {
+            return vec![Problem::Unreadable {
+                image: path,
+                reason: err.full_message(),
+            }];
+        }
+        let mut problems = vec![];
+        let overrides = section.overrides.for_image(image);
+        match SourceImage::probe(image, &overrides).and_then(|source| {
+            source.check_decodable()?;
+            Ok(source.oriented_dimensions())
+        }) {
+            Ok((width, height)) => {
+                if width.min(height) < MIN_SIDE || u64::from(width) * u64::from(height) > MAX_PIXELS
+                {
+                    problems.push(Problem::SuspiciousSize {
+                        image: path.clone(),
+                        width,
+                        height,
+                    });
+                }
+                if let Some(crop) = overrides.crop.filter(|c| !c.fits_within(width, height)) {
+                    problems.push(Problem::CropOutside {
+                        image: path.clone(),
+                        crop,
+                        width,
+                        height,
+                    });
+                }
+            }
+            Err(err) => problems.push(Problem::Undecodable {
+                image: path.clone(),
+                reason: err.full_message(),
+            }),
+        }
+        match self
+            .layout
+            .naming
+            .output_paths(context, &self.layout.variants)
+        {
+            Ok(output_paths) => {
+                for output_path in output_paths.into_values() {
+                    match self.output_paths.get(&output_path) {
+                        Some(other_image) => problems.push(Problem::NameCollision {
+                            image: path.clone(),
+                            output_path: self.layout.output_root.join(&output_path),
+                            other_image: other_image.clone(),
+                        }),
+                        None => {
+                            self.output_paths.insert(output_path, path.clone());
+                        }
+                    }
+                }
+            }
+            Err(err) => problems.push(Problem::Unnameable {
+                image: path,
+                reason: err.full_message(),
+            }),
+        }
+        problems
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use speculoos::prelude::*;
+
+    use crate::conversion::OutputLayout;
+    use crate::image_operations::Variant;
+    use crate::images::SourceImagePath;
+    use crate::input::{InputOptions, Minutes, Section};
+    use crate::overrides::{Crop, SectionOverrides};
+    use crate::validation::{validate, Problem};
+
+    fn section(name: &str, images: &[&Path], subsections: Vec<Section>) -> Section {
+        Section {
+            name: name.to_string(),
+            image_files: images
+                .iter()
+                .map(|p| SourceImagePath::new(p.to_path_buf()))
+                .collect(),
+            overrides: SectionOverrides::default(),
+            subsections,
+        }
+    }
+
+    fn layout(naming: &str) -> anyhow::Result<OutputLayout> {
+        anyhow::Ok(OutputLayout {
+            output_root: PathBuf::from("/out"),
+            variants: vec!["small=0.3".parse::<Variant>().map_err(anyhow::Error::msg)?],
+            naming: naming.parse().map_err(anyhow::Error::msg)?,
+        })
+    }
+
+    #[test]
+    fn valid_minutes_have_no_problems() -> anyhow::Result<()> {
+        let image = Path::new("./src/empty-100x200.jpg");
+        let minutes = Minutes {
+            sections: vec![section("a", &[image], vec![])],
+            ignored_files: vec![],
+        };
+
+        let report = validate(&minutes, &layout("{section}/{stem}_{variant}.{ext}")?);
+
+        assert_that!(report.is_ok()).is_true();
+        assert_that!(report.image_count).is_equal_to(1);
+        Ok(())
+    }
+
+    #[test]
+    fn all_problems_are_collected_by_section() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let broken = dir.path().join("broken.jpg");
+        fs_err::write(&broken, "no image")?;
+        let tiny = dir.path().join("tiny.jpg");
+        image::RgbImage::new(10, 10).save(&tiny)?;
+        let missing = dir.path().join("missing.jpg");
+        let no_extension = dir.path().join("no_extension");
+        let image = Path::new("./src/empty-100x200.jpg");
+        let minutes = Minutes {
+            sections: vec![
+                section("a", &[image, &broken, &missing], vec![]),
+                section(
+                    "b",
+                    &[],
+                    vec![
+                        section("c", &[image, &tiny, &no_extension], vec![]),
+                        section("d", &[], vec![]),
+                    ],
+                ),
+            ],
+            ignored_files: vec![],
+        };
+
+        let report = validate(&minutes, &layout("{stem}_{variant}.{ext}")?);
+
+        assert_that!(report.problem_count()).is_equal_to(6);
+        let paths: Vec<_> = report
+            .sections
+            .iter()
+            .map(|s| s.section_path.join("/"))
+            .collect();
+        assert_that!(paths).is_equal_to(vec![
+            "a".to_string(),
+            "b/c".to_string(),
+            "b/d".to_string(),
+        ]);
+        assert_that!(matches!(
+            report.sections[0].problems[..],
+            [Problem::Undecodable { .. }, Problem::Unreadable { .. }]
+        ))
+        .is_true();
+        assert_that!(report.sections[1].problems).contains(Problem::NameCollision {
+            image: image.to_path_buf(),
+            output_path: PathBuf::from("/out/empty-100x200_small.jpg"),
+            other_image: image.to_path_buf(),
+        });
+        assert_that!(report.sections[1].problems).contains(Problem::SuspiciousSize {
+            image: tiny,
+            width: 10,
+            height: 10,
+        });
+        assert_that!(report.sections[1].problems).contains(Problem::MissingExtension {
+            image: no_extension,
+        });
+        assert_that!(report.sections[2].problems).is_equal_to(vec![Problem::EmptySection]);
+        assert_that!(report.to_string()).contains("b / c:\n  - ");
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_images_and_crops_outside_are_problems() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let complete = dir.path().join("complete.jpg");
+        image::RgbImage::from_fn(256, 256, |x, y| {
+            image::Rgb([x as u8, y as u8, (x ^ y) as u8])
+        })
+        .save(&complete)?;
+        let content = fs_err::read(&complete)?;
+        let truncated = dir.path().join("truncated.jpg");
+        fs_err::write(&truncated, &content[..content.len() / 2])?;
+        let cropped = Path::new("./src/empty-100x200.jpg");
+        let mut section = section("a", &[&complete, &truncated, cropped], vec![]);
+        section.overrides =
+            SectionOverrides::parse("[\"empty-100x200.jpg\"]\ncrop = [50, 0, 60, 200]\n")?;
+        let minutes = Minutes {
+            sections: vec![section],
+            ignored_files: vec![],
+        };
+
+        let report = validate(&minutes, &layout("{stem}_{variant}.{ext}")?);
+
+        assert_that!(report.problem_count()).is_equal_to(2);
+        assert_that!(matches!(
+            &report.sections[0].problems[0],
+            Problem::Undecodable { image, .. } if image == &truncated
+        ))
+        .is_true();
+        assert_that!(report.sections[0].problems[1]).is_equal_to(Problem::CropOutside {
+            image: cropped.to_path_buf(),
+            crop: Crop {
+                x: 50,
+                y: 0,
+                width: 60,
+                height: 200,
+            },
+            width: 100,
+            height: 200,
+        });
+        Ok(())
+    }
+
+    #[test]
+    fn overrides_of_missing_files_are_problems() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs_err::create_dir(dir.path().join("a"))?;
+        for name in ["image_1.jpg", "image_2.jpg"] {
+            fs_err::copy("./src/empty-100x200.jpg", dir.path().join("a").join(name))?;
+        }
+        fs_err::write(
+            dir.path().join("a/overrides.toml"),
+            "[\"image_1.JPG\"]\nrotate = 90\n\n[\"image_2.jpg\"]\nskip = true\n",
+        )?;
+        let minutes = Minutes::read(dir.path(), &InputOptions::default())?;
+
+        let report = validate(&minutes, &layout("{stem}_{variant}.{ext}")?);
+
+        assert_that!(report.sections[0].problems).is_equal_to(vec![Problem::UnmatchedOverride {
+            key: "image_1.JPG".to_string(),
+        }]);
+        assert_that!(report.to_string()).contains("[\"image_1.JPG\"] matches no file");
+        Ok(())
+    }
+}
@@ -0,0 +1,332 @@
+use std::collections::BTreeSet;
+use std::fmt::{Display, Formatter};
+use std::path::{Component, Path, PathBuf};
+
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use pulldown_cmark::{Event, Parser, Tag};
+
+use crate::config::OutputFormat;
+use crate::error::Error;
+use crate::output::PATH_ENCODING;
+use crate::publish::local_files;
+
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub enum LinkProblem {
+    /// `url` refers to `path` below the output root, which does not exist.
+    Missing { url: String, path: PathBuf },
+    /// A file below the output root that the document does not refer to.
+    Orphaned { path: PathBuf },
+    /// The HEAD request of `url` below the base URL failed.
+    Unreachable { url: String, reason: String },
+}
+
+impl Display for LinkProblem {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            LinkProblem::Missing { url, path } => {
+                write!(f, "{}: {} does not exist", url, path.to_string_lossy())
+            }
+            LinkProblem::Orphaned { path } => write!(
+                f,
+                "{}: is not referred to by the document",
+                path.to_string_lossy()
+            ),
+            LinkProblem::Unreachable { url, reason } => write!(f, "{}: {}", url, reason),
+        }
+    }
+}
+
+#[derive(Eq, PartialEq, Debug, Clone, Default)]
+pub struct VerifyReport {
+    /// Distinct URLs of the document below the online base path.
+    pub link_count: usize,
+    pub problems: Vec<LinkProblem>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+impl Display for VerifyReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for problem in &self.problems {
+            writeln!(f, "  - {}", problem)?;
+        }
+        Ok(())
+    }
+}
+
+/// Where the files the document refers to are expected.
+#[derive(Eq, PartialEq, Debug, Clone)]
+pub struct VerifyOptions {
+    pub output_root: PathBuf,
+    pub online_base_path: String,
+    /// Files below the output root that are not referred to by design, e.g. the
+    /// document itself.
+    pub other_files: Vec<PathBuf>,
+    /// Also requests every referred file with HEAD below this URL, e.g. where the
+    /// output was published.
+    pub base_url: Option<String>,
+}
+
+/// Checks that every URL of `document` below the online base path refers to a file
+/// below the output root and that every file there is referred to. Hidden files, e.g.
+/// the state of `publish`, are not expected to be referred to. Other URLs are ignored.
+pub fn verify(
+    document: &str,
+    format: OutputFormat,
+    options: &VerifyOptions,
+) -> Result<VerifyReport, Error> {
+    let prefix = format!("{}/", options.online_base_path.trim_end_matches('/'));
+    let mut report = VerifyReport::default();
+    let mut referred = BTreeSet::new();
+    let mut seen = BTreeSet::new();
+    for url in document_urls(document, format) {
+        let Some(relative) = url.strip_prefix(&prefix) else {
+            continue;
+        };
+        if !seen.insert(url.clone()) {
+            continue;
+        }
+        let relative = percent_decode_str(relative).decode_utf8_lossy().to_string();
+        let path = options.output_root.join(&relative);
+        let inside = Path::new(&relative)
+            .components()
+            .all(|c| matches!(c, Component::Normal(_)));
+        if !inside || !path.is_file() {
+            report.problems.push(LinkProblem::Missing {
+                url: url.clone(),
+                path,
+            });
+        }
+        if let Some(base_url) = &options.base_url {
+            let online_url = format!(
+                "{}/{}",
+                base_url.trim_end_matches('/'),
+                utf8_percent_encode(&relative, PATH_ENCODING)
+            );
+            if let Some(reason) = head(&online_url) {
+                report.problems.push(LinkProblem::Unreachable {
+                    url: online_url,
+                    reason,
+                });
+            }
+        }
+        referred.insert(relative);
+    }
+    report.link_count = seen.len();
+
+    let other_files: BTreeSet<PathBuf> = options
+        .other_files
+        .iter()
+        .filter_map(|p| fs_err::canonicalize(p).ok())
+        .collect();
+    for (relative, path) in local_files(&options.output_root)? {
+        let other = fs_err::canonicalize(&path).is_ok_and(|p| other_files.contains(&p));
+        if !referred.contains(&relative) && !other {
+            report.problems.push(LinkProblem::Orphaned { path });
+        }
+    }
+    Ok(report)
+}
+
+/// `None` if the request succeeds, otherwise why not.
+fn head(url: &str) -> Option<String> {
+    match ureq::head(url).call() {
+        Ok(_) => None,
+        Err(ureq::Error::Status(status, response)) => {
+            Some(format!("{} {}", status, response.status_text()))
+        }
+        // Without the URL, which the message of the error starts with.
+        Err(ureq::Error::Transport(transport)) => Some(
+            [transport.kind().to_string()]
+                .into_iter()
+                .chain(transport.message().map(str::to_string))
+                .chain(std::error::Error::source(&transport).map(|e| e.to_string()))
+                .collect::<Vec<_>>()
+                .join(": "),
+        ),
+    }
+}
+
+/// The URLs of links and images in document order. In Markdown, these include the
+/// attributes of embedded HTML.
+fn document_urls(document: &str, format: OutputFormat) -> Vec<String> {
+    if format == OutputFormat::Html {
+        return html_urls(document);
+    }
+    let mut urls = vec![];
+    for event in Parser::new(document) {
+        match event {
+            Event::Start(Tag::Link { dest_url, .. } | Tag::Image { dest_url, .. }) => {
+                urls.push(dest_url.to_string())
+            }
+            Event::Html(html) | Event::InlineHtml(html) => urls.extend(html_urls(&html)),
+            _ => {}
+        }
+    }
+    urls
+}
+
+/// The values of the `href`, `src` and `srcset` attributes, which are expected in
+/// double quotes as written by this crate.
+fn html_urls(html: &str) -> Vec<String> {
+    let mut urls = vec![];
+    let mut rest = html;
+    while let Some(start) = rest.find("=\"") {
+        let name = rest[..start]
+            .rsplit(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        let value_and_rest = &rest[start + 2..];
+        let Some(end) = value_and_rest.find('"') else {
+            break;
+        };
+        let value = unescape(&value_and_rest[..end]);
+        match name.as_str() {
+            "href" | "src" => urls.push(value),
+            "srcset" => urls.extend(
+                value
+                    .split(',')
+                    .filter_map(|candidate| candidate.split_whitespace().next())
+                    .map(str::to_string),
+            ),
+            _ => {}
+        }
+        rest = &value_and_rest[end + 1..];
+    }
+    urls
+}
+
+fn unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use speculoos::prelude::*;
+
+    use crate::config::OutputFormat;
+    use crate::conversion::test_support::{build_markdown, copy_test_images, layout};
+    use crate::preview::PreviewServer;
+    use crate::verify::{document_urls, verify, LinkProblem, VerifyOptions};
+
+    fn options(output_root: &Path) -> VerifyOptions {
+        VerifyOptions {
+            output_root: output_root.to_path_buf(),
+            online_base_path: "https://example.org/minutes".to_string(),
+            other_files: vec![],
+            base_url: None,
+        }
+    }
+
+    #[test]
+    fn finds_the_urls_of_markdown_and_html_documents() {
+        let markdown = "# Q&A\n\n[![Q&A](/m/1_small.jpg)](/m/1_large.jpg)\n\n\
+            <a href=\"/m/2_large.jpg\"><img src=\"/m/2_small.jpg\" alt=\"Q&amp;A\"></a>\n";
+        let html = "<h1>Q&amp;A</h1>\n<a href=\"/m/Q&amp;A/1_large.jpg\"><img \
+            src=\"/m/1_small.jpg\" srcset=\"/m/1_small.jpg 320w, /m/1_medium.jpg 1024w\" \
+            sizes=\"100vw\" alt=\"Q&amp;A\"></a>\n";
+
+        assert_that!(document_urls(markdown, OutputFormat::Markdown)).is_equal_to(vec![
+            "/m/1_large.jpg".to_string(),
+            "/m/1_small.jpg".to_string(),
+            "/m/2_large.jpg".to_string(),
+            "/m/2_small.jpg".to_string(),
+        ]);
+        assert_that!(document_urls(html, OutputFormat::Html)).is_equal_to(vec![
+            "/m/Q&A/1_large.jpg".to_string(),
+            "/m/1_small.jpg".to_string(),
+            "/m/1_small.jpg".to_string(),
+            "/m/1_medium.jpg".to_string(),
+        ]);
+    }
+
+    #[test]
+    fn reports_missing_and_orphaned_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        fs_err::create_dir(dir.path().join("section"))?;
+        for file in [
+            "1_small.jpg",
+            "2_small.jpg",
+            ".minutes-publish.json",
+            "minutes.md",
+        ] {
+            fs_err::write(dir.path().join("section").join(file), "")?;
+        }
+        let document = "[![section](https://example.org/minutes/section/1_small.jpg)]\
+            (https://example.org/minutes/section/1_large.jpg)\n\n\
+            [elsewhere](https://example.org/other/1.jpg)\n";
+        let options = VerifyOptions {
+            other_files: vec![dir.path().join("section/minutes.md")],
+            ..options(dir.path())
+        };
+
+        let report = verify(document, OutputFormat::Markdown, &options)?;
+
+        assert_that!(report.link_count).is_equal_to(2);
+        assert_that!(report.problems).is_equal_to(vec![
+            LinkProblem::Missing {
+                url: "https://example.org/minutes/section/1_large.jpg".to_string(),
+                path: dir.path().join("section/1_large.jpg"),
+            },
+            LinkProblem::Orphaned {
+                path: dir.path().join("section/2_small.jpg"),
+            },
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn requests_the_files_below_the_base_url() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let published = tempfile::tempdir()?;
+        fs_err::create_dir(dir.path().join("section 1"))?;
+        fs_err::create_dir(published.path().join("section 1"))?;
+        for file in ["1_small.jpg", "1_large.jpg"] {
+            fs_err::write(dir.path().join("section 1").join(file), "")?;
+        }
+        fs_err::write(published.path().join("section 1/1_small.jpg"), "")?;
+        let server = PreviewServer::bind(0, published.path())?;
+        let base_url = server.url();
+        std::thread::spawn(move || server.run());
+        let document = "<a href=\"https://example.org/minutes/section%201/1_large.jpg\">\
+            <img src=\"https://example.org/minutes/section%201/1_small.jpg\"></a>";
+        let options = VerifyOptions {
+            base_url: Some(base_url.clone()),
+            ..options(dir.path())
+        };
+
+        let report = verify(document, OutputFormat::Html, &options)?;
+
+        assert_that!(report.problems).is_equal_to(vec![LinkProblem::Unreachable {
+            url: format!("{}/section%201/1_large.jpg", base_url),
+            reason: "404 Not Found".to_string(),
+        }]);
+        Ok(())
+    }
+
+    #[test]
+    fn accepts_the_document_built_for_sections_with_spaces() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("input");
+        let output = dir.path().join("output");
+        copy_test_images(&input, &["Session 1/a.jpg", "Track A/Talk 1/b.jpg"])?;
+        let layout = layout(&output, &["small=0.5", "large=0.5"])?;
+        let document = build_markdown(&input, &layout, "https://example.org/minutes")?;
+
+        let report = verify(&document, OutputFormat::Markdown, &options(&output))?;
+
+        assert_that!(report.link_count).is_equal_to(4);
+        assert_that!(report.problems).is_equal_to(vec![]);
+        Ok(())
+    }
+}
@@ -0,0 +1,318 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, SystemTime};
+
+use notify::event::ModifyKind;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+use crate::conversion::MinutesForConversion;
+use crate::error::Error;
+use crate::overrides::ImageOverrides;
+use crate::plan::{ConversionReport, ExistingFiles};
+
+/// Changes that follow each other within this time are handled together, e.g. while
+/// a batch of photos is copied.
+const QUIET_PERIOD: Duration = Duration::from_millis(500);
+
+/// Notices changes below the input, e.g. photos that are added.
+pub struct InputWatcher {
+    root: PathBuf,
+    // Stops watching when dropped.
+    _watcher: RecommendedWatcher,
+    events: Receiver<notify::Result<notify::Event>>,
+}
+
+impl InputWatcher {
+    pub fn new(root: &Path) -> Result<Self, Error> {
+        let (sender, events) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(sender).map_err(watch_error(root))?;
+        watcher
+            .watch(root, RecursiveMode::Recursive)
+            .map_err(watch_error(root))?;
+        Ok(Self {
+            root: root.to_path_buf(),
+            _watcher: watcher,
+            events,
+        })
+    }
+
+    /// Blocks until something was added, changed or removed and no further change
+    /// followed for a moment. Reading the input, as the conversion does, is no change.
+    pub fn wait(&self) -> Result<(), Error> {
+        loop {
+            let event = self.events.recv().map_err(|_| self.stopped())?;
+            if self.is_change(event)? {
+                break;
+            }
+        }
+        loop {
+            match self.events.recv_timeout(QUIET_PERIOD) {
+                Ok(event) => {
+                    self.is_change(event)?;
+                }
+                Err(RecvTimeoutError::Timeout) => return Ok(()),
+                Err(RecvTimeoutError::Disconnected) => return Err(self.stopped()),
+            }
+        }
+    }
+
+    fn is_change(&self, event: notify::Result<notify::Event>) -> Result<bool, Error> {
+        let event = event.map_err(watch_error(&self.root))?;
+        Ok(!matches!(
+            event.kind,
+            EventKind::Access(_) | EventKind::Modify(ModifyKind::Metadata(_))
+        ))
+    }
+
+    fn stopped(&self) -> Error {
+        Error::Watch {
+            path: self.root.clone(),
+            source: notify::Error::io(io::Error::other("the watcher stopped")),
+        }
+    }
+}
+
+fn watch_error(path: &Path) -> impl FnOnce(notify::Error) -> Error {
+    let path = path.to_path_buf();
+    move |source| Error::Watch { path, source }
+}
+
+/// Modification time and size of a source image, or of the archive it is read from.
+type FileState = (SystemTime, u64);
+
+#[derive(PartialEq, Debug)]
+struct ConvertedImage {
+    state: FileState,
+    overrides: ImageOverrides,
+    outputs: BTreeSet<PathBuf>,
+}
+
+/// The images converted so far, so that after a change of the input only the images
+/// that were added or changed are converted again.
+#[derive(Debug, Default)]
+pub struct ConvertedImages {
+    images: BTreeMap<PathBuf, ConvertedImage>,
+    failed: BTreeSet<PathBuf>,
+    dimensions: BTreeMap<PathBuf, (u32, u32)>,
+}
+
+/// What [`ConvertedImages::update`] found. The images only count as converted once
+/// this is passed to [`ConvertedImages::record`].
+#[derive(Debug)]
+pub struct Changes {
+    /// Source images that were added or changed.
+    pub changed: BTreeSet<PathBuf>,
+    /// Files generated before for changed and removed images.
+    pub removed: Vec<PathBuf>,
+    images: BTreeMap<PathBuf, ConvertedImage>,
+}
+
+impl ConvertedImages {
+    /// Returns the source images of `for_conversion` that were added or changed since
+    /// the last recorded conversion, including those whose overrides or generated file
+    /// names changed. Removes the files generated before for changed and removed images
+    /// and returns them as well.
+    pub fn update(&mut self, for_conversion: &MinutesForConversion) -> Result<Changes, Error> {
+        let mut current = BTreeMap::new();
+        for f in for_conversion.image_files() {
+            let path = f.source_image_path.path();
+            let image = ConvertedImage {
+                state: file_state(path)?,
+                overrides: f.overrides,
+                outputs: f.variants.values().cloned().collect(),
+            };
+            current.insert(path.to_path_buf(), image);
+        }
+        let changed: BTreeSet<PathBuf> = current
+            .iter()
+            .filter(|(path, image)| self.images.get(*path) != Some(image))
+            .map(|(path, _)| path.clone())
+            .collect();
+        let outdated: Vec<PathBuf> = self
+            .images
+            .iter()
+            .filter(|(path, _)| changed.contains(*path) || !current.contains_key(*path))
+            .flat_map(|(_, image)| image.outputs.iter().cloned())
+            .collect();
+        let mut removed = vec![];
+        for path in outdated {
+            self.dimensions.remove(&path);
+            match fs_err::remove_file(&path) {
+                Ok(()) => removed.push(path),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => return Err(Error::write(&path)(err)),
+            }
+        }
+        Ok(Changes {
+            changed,
+            removed,
+            images: current,
+        })
+    }
+
+    /// Output files that exist before anything was converted, e.g. by an earlier
+    /// `build`, are skipped instead of failing on every change.
+    pub fn existing_files(&self, existing: ExistingFiles) -> ExistingFiles {
+        match existing {
+            ExistingFiles::Fail if self.images.is_empty() => ExistingFiles::Skip,
+            existing => existing,
+        }
+    }
+
+    /// Considers the images of `changes` converted and takes the failed images and the
+    /// dimensions of the generated files from their conversion.
+    pub fn record(&mut self, changes: Changes, report: &ConversionReport) {
+        self.failed
+            .retain(|path| changes.images.contains_key(path) && !changes.changed.contains(path));
+        self.failed
+            .extend(report.failed.iter().map(|f| f.source_image_path.clone()));
+        self.dimensions.extend(report.dimensions.clone());
+        self.images = changes.images;
+    }
+
+    /// Leaves out the images that could not be converted and records the dimensions of
+    /// the generated files, so `for_conversion` describes the files as they are.
+    pub fn apply(&self, for_conversion: &mut MinutesForConversion) {
+        for_conversion.retain_images(|f| !self.failed.contains(f.source_image_path.path()));
+        for_conversion.record_dimensions(&self.dimensions);
+    }
+}
+
+/// Images in an archive have no state of their own, they change with the archive.
+fn file_state(path: &Path) -> Result<FileState, Error> {
+    let metadata = path
+        .ancestors()
+        .find_map(|p| fs_err::metadata(p).ok())
+        .ok_or_else(|| Error::SourceMissing {
+            path: path.to_path_buf(),
+        })?;
+    let modified = metadata.modified().map_err(Error::read(path))?;
+    Ok((modified, metadata.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use speculoos::prelude::*;
+
+    use crate::conversion::test_support::layout;
+    use crate::conversion::{create_minutes_for_conversion, OutputLayout};
+    use crate::input::{InputOptions, Minutes};
+    use crate::plan::{ConversionPlan, ExistingFiles};
+    use crate::watch::ConvertedImages;
+
+    /// Reads the input, converts what changed and returns the changed source images
+    /// and the removed files by file name.
+    fn convert_changes(
+        converted: &mut ConvertedImages,
+        input: &Path,
+        layout: &OutputLayout,
+    ) -> anyhow::Result<(Vec<String>, Vec<String>)> {
+        let minutes = Minutes::read(input, &InputOptions::default())?;
+        let mut for_conversion = create_minutes_for_conversion(&minutes, layout)?;
+        let changes = converted.update(&for_conversion)?;
+        let existing = converted.existing_files(ExistingFiles::Fail);
+        let mut plan = ConversionPlan::create(&for_conversion, layout, existing);
+        plan.files
+            .retain(|f| changes.changed.contains(f.source_image_path.path()));
+        let report = plan.execute(true, |_| {})?;
+        let file_name = |p: &PathBuf| p.file_name().unwrap().to_string_lossy().to_string();
+        let result = (
+            changes.changed.iter().map(file_name).collect(),
+            changes.removed.iter().map(file_name).collect(),
+        );
+        converted.record(changes, &report);
+        converted.apply(&mut for_conversion);
+        Ok(result)
+    }
+
+    #[test]
+    fn converts_only_added_and_changed_images() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("input");
+        let output = dir.path().join("output");
+        fs_err::create_dir_all(input.join("section"))?;
+        fs_err::copy("src/empty-100x200.jpg", input.join("section/1.jpg"))?;
+        fs_err::copy("src/empty-100x200.jpg", input.join("section/2.jpg"))?;
+        let layout = layout(&output, &["small=0.5"])?;
+        let mut converted = ConvertedImages::default();
+
+        let first = convert_changes(&mut converted, &input, &layout)?;
+        let unchanged = convert_changes(&mut converted, &input, &layout)?;
+        fs_err::copy("src/empty-100x200.jpg", input.join("section/3.jpg"))?;
+        fs_err::write(
+            input.join("section/overrides.toml"),
+            "[\"2.jpg\"]\nrotate = 90\n",
+        )?;
+        let added = convert_changes(&mut converted, &input, &layout)?;
+        fs_err::remove_file(input.join("section/1.jpg"))?;
+        let removed = convert_changes(&mut converted, &input, &layout)?;
+
+        assert_that!(first.0).is_equal_to(vec!["1.jpg".to_string(), "2.jpg".to_string()]);
+        assert_that!(unchanged).is_equal_to((vec![], vec![]));
+        assert_that!(added).is_equal_to((
+            vec!["2.jpg".to_string(), "3.jpg".to_string()],
+            vec!["2_small.jpg".to_string()],
+        ));
+        assert_that!(removed).is_equal_to((vec![], vec!["1_small.jpg".to_string()]));
+        assert_that!(output.join("section/1_small.jpg").exists()).is_false();
+        assert_that!(output.join("section/2_small.jpg").exists()).is_true();
+        Ok(())
+    }
+
+    #[test]
+    fn starts_on_an_output_with_the_generated_files() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("input");
+        fs_err::create_dir_all(input.join("section"))?;
+        fs_err::copy("src/empty-100x200.jpg", input.join("section/1.jpg"))?;
+        let layout = layout(&dir.path().join("output"), &["small=0.5"])?;
+        let minutes = Minutes::read(&input, &InputOptions::default())?;
+        let for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+        ConversionPlan::create(&for_conversion, &layout, ExistingFiles::Fail)
+            .execute(false, |_| {})?;
+        let mut converted = ConvertedImages::default();
+
+        let unrecorded = converted.update(&for_conversion)?;
+        let first = convert_changes(&mut converted, &input, &layout)?;
+        let unchanged = convert_changes(&mut converted, &input, &layout)?;
+
+        assert_that!(unrecorded.changed.len()).is_equal_to(1);
+        assert_that!(first).is_equal_to((vec!["1.jpg".to_string()], vec![]));
+        assert_that!(unchanged).is_equal_to((vec![], vec![]));
+        assert_that!(converted.existing_files(ExistingFiles::Fail))
+            .is_equal_to(ExistingFiles::Fail);
+        Ok(())
+    }
+
+    #[test]
+    fn failed_images_are_left_out_until_they_change() -> anyhow::Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("input");
+        fs_err::create_dir_all(input.join("section"))?;
+        fs_err::write(input.join("section/1.jpg"), "not a jpeg")?;
+        let layout = layout(&dir.path().join("output"), &["small=0.5"])?;
+        let mut converted = ConvertedImages::default();
+
+        convert_changes(&mut converted, &input, &layout)?;
+        let minutes = Minutes::read(&input, &InputOptions::default())?;
+        let mut for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+        converted.apply(&mut for_conversion);
+        let failed_images = for_conversion.image_files().len();
+        fs_err::copy("src/empty-100x200.jpg", input.join("section/1.jpg"))?;
+        let fixed = convert_changes(&mut converted, &input, &layout)?;
+        let mut for_conversion = create_minutes_for_conversion(&minutes, &layout)?;
+        converted.apply(&mut for_conversion);
+
+        assert_that!(failed_images).is_equal_to(0);
+        assert_that!(fixed.0).is_equal_to(vec!["1.jpg".to_string()]);
+        assert_that!(for_conversion.image_files()[0]
+            .dimensions
+            .contains_key("small"))
+        .is_true();
+        Ok(())
+    }
+}